@@ -0,0 +1,53 @@
+//! Render a batch of quotes and print the time taken. Run with `cargo bench`.
+
+use make_quote::{FontSet, ImgConfig, Lines, QuoteProducer, TextDrawInfo};
+use std::time::Instant;
+
+const QUOTES: [&str; 4] = [
+    "大家好，今天来点大家想看的东西。",
+    "The quick brown fox jumps over the lazy dog, again and again and again until it gets tired.",
+    "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt.",
+    "一二三四五六七八九十一二三四五六七八九十一二三四五六七八九十一二三四五六七八九十",
+];
+
+fn main() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+
+    // Text wrapping alone, which measures the text char by char
+    let parsed = rusttype::Font::try_from_bytes(&font).unwrap();
+    let start = Instant::now();
+    for _ in 0..50 {
+        for quote in QUOTES {
+            let info = TextDrawInfo::builder()
+                .text(quote)
+                .rgba([255, 255, 255, 255])
+                .scale(140.0)
+                .font(&parsed)
+                .build();
+            std::hint::black_box(Lines::new(&info, 1200));
+        }
+    }
+    println!("wrap 200 quotes: {:?}", start.elapsed());
+
+    // The same renders without and with the glyph cache of the producer
+    for (label, capacity) in [("without glyph cache", 0), ("with glyph cache", 8192)] {
+        let producer = QuoteProducer::builder()
+            .font(FontSet::single(&font, 0).unwrap())
+            .output_size(960, 540)
+            .font_scale(70.0)
+            .glyph_cache(capacity)
+            .build();
+        let start = Instant::now();
+        for _ in 0..5 {
+            for quote in QUOTES {
+                let config = ImgConfig::builder()
+                    .username("@ksyxmeow")
+                    .avatar("./assets/avatar.png")
+                    .quote(quote)
+                    .build();
+                std::hint::black_box(producer.make_raw_image(&config).unwrap());
+            }
+        }
+        println!("render 20 quotes {label}: {:?}", start.elapsed());
+    }
+}
@@ -1,7 +1,76 @@
-use super::TextDrawInfo;
-use image::{imageops, imageops::FilterType, Rgba, RgbaImage};
+use super::{ink_vertical_bounds, round_corners, TextDrawInfo};
+use image::codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder};
+use image::{imageops, imageops::FilterType, AnimationDecoder, ImageFormat, ImageResult};
+use image::{Rgba, RgbaImage};
+use rusttype::Scale;
+use std::io::Cursor;
 use typed_builder::TypedBuilder;
 
+/// Decode the avatar image. Animated GIF, APNG and WebP will only have their first frame
+/// decoded.
+pub fn decode_avatar(buffer: &[u8]) -> ImageResult<RgbaImage> {
+    let first_frame = match image::guess_format(buffer)? {
+        ImageFormat::Gif => GifDecoder::new(Cursor::new(buffer))?.into_frames().next(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(buffer))?;
+            if decoder.is_apng() {
+                decoder.apng().into_frames().next()
+            } else {
+                None
+            }
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(Cursor::new(buffer))?;
+            if decoder.has_animation() {
+                decoder.into_frames().next()
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+
+    match first_frame {
+        Some(frame) => Ok(frame?.into_buffer()),
+        #[cfg(feature = "exif")]
+        None => Ok(apply_orientation(
+            image::load_from_memory(buffer)?.into_rgba8(),
+            exif_orientation(buffer),
+        )),
+        #[cfg(not(feature = "exif"))]
+        None => Ok(image::load_from_memory(buffer)?.into_rgba8()),
+    }
+}
+
+/// Read the EXIF orientation of the photo, 1 means it is already upright.
+#[cfg(feature = "exif")]
+fn exif_orientation(buffer: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(buffer))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+/// Rotate and flip the image so it is displayed upright with the given EXIF orientation.
+#[cfg(feature = "exif")]
+fn apply_orientation(img: RgbaImage, orientation: u32) -> RgbaImage {
+    match orientation {
+        2 => imageops::flip_horizontal(&img),
+        3 => imageops::rotate180(&img),
+        4 => imageops::flip_vertical(&img),
+        5 => imageops::flip_horizontal(&imageops::rotate90(&img)),
+        6 => imageops::rotate90(&img),
+        7 => imageops::flip_horizontal(&imageops::rotate270(&img)),
+        8 => imageops::rotate270(&img),
+        _ => img,
+    }
+}
+
+/// Scale the avatar to the background height and crop it for the avatar region.
 #[derive(TypedBuilder)]
 #[builder(build_method(into = RgbaImage))]
 pub struct Avatar {
@@ -10,39 +79,290 @@ pub struct Avatar {
     bg_height: u32,
     #[builder(default = true)]
     enable_crop: bool,
+    #[builder(default)]
+    filter: AvatarFilter,
+    /// Which part of the avatar is kept after cropping
+    #[builder(default)]
+    crop_anchor: CropAnchor,
+    /// Keep only this `(x, y, width, height)` region of the image, then scale it to the background
+    /// height. The automatic crop is skipped when it is set. The rect is clamped to the image, and
+    /// the whole image is kept when nothing of it is left.
+    #[builder(default)]
+    crop_rect: Option<(u32, u32, u32, u32)>,
+    /// Scale and crop the avatar to exactly this `(width, height)`, instead of keeping its aspect
+    /// ratio at the background height. The crop follows `crop_anchor` horizontally.
+    #[builder(default)]
+    region: Option<(u32, u32)>,
+    /// Crop the sides of an avatar wider than this, following `crop_anchor`. It applies to the
+    /// uncropped avatar too, so a very wide image can't push the quote out of the background.
+    #[builder(default)]
+    max_width: Option<u32>,
+    /// Magnify the scaled avatar by this factor and show the middle of it, values below 1 are
+    /// treated as 1.
+    #[builder(default = 1.0)]
+    zoom: f32,
+    /// Move the window shown after zooming by `(x, y)` pixels of the zoomed image. It stops at the
+    /// zoomed image edges, so the avatar stays covered.
+    #[builder(default)]
+    offset: (i32, i32),
+    /// Scale the avatar by its exact aspect ratio before the automatic crop. By default the width
+    /// is the background height times the whole number ratio of the image, and only images
+    /// taller than wide use the exact ratio.
+    #[builder(default = false)]
+    keep_aspect_ratio: bool,
+    #[builder(default = FilterType::CatmullRom)]
+    resize_filter: FilterType,
+}
+
+/// Where the kept region sits when the avatar get cropped.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CropAnchor {
+    /// Keep the left 3/4 of the avatar.
+    Left,
+    /// Crop 1/8 from both sides.
+    Center,
+    /// Keep the right 3/4 of the avatar.
+    #[default]
+    Right,
+}
+
+/// The background shape drawn behind the letter of letter avatars.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AvatarShape {
+    #[default]
+    Circle,
+    /// A square with its corners rounded by the given radius in pixels.
+    RoundedSquare {
+        radius: u32,
+    },
+    Square,
+}
+
+/// Color filter applied to the avatar before it get composited into the output image.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AvatarFilter {
+    #[default]
+    None,
+    Grayscale,
+    Sepia,
+    /// Map the avatar luminance between the dark color and the light color.
+    Duotone { dark: Rgba<u8>, light: Rgba<u8> },
+}
+
+impl AvatarFilter {
+    pub(crate) fn apply(self, img: RgbaImage) -> RgbaImage {
+        if self == AvatarFilter::None {
+            return img;
+        }
+
+        let luma = imageops::grayscale(&img);
+        let mut img = img;
+        for (pixel, l) in img.pixels_mut().zip(luma.pixels()) {
+            let l = l.0[0];
+            let [r, g, b, a] = pixel.0;
+            pixel.0 = match self {
+                AvatarFilter::None => [r, g, b, a],
+                AvatarFilter::Grayscale => [l, l, l, a],
+                AvatarFilter::Sepia => {
+                    let (r, g, b) = (r as f32, g as f32, b as f32);
+                    let tone = |x: f32| x.min(255.0) as u8;
+                    [
+                        tone(0.393 * r + 0.769 * g + 0.189 * b),
+                        tone(0.349 * r + 0.686 * g + 0.168 * b),
+                        tone(0.272 * r + 0.534 * g + 0.131 * b),
+                        a,
+                    ]
+                }
+                AvatarFilter::Duotone { dark, light } => {
+                    let t = l as f32 / 255.0;
+                    let mix = |d: u8, l: u8| (d as f32 + (l as f32 - d as f32) * t).round() as u8;
+                    [
+                        mix(dark.0[0], light.0[0]),
+                        mix(dark.0[1], light.0[1]),
+                        mix(dark.0[2], light.0[2]),
+                        a,
+                    ]
+                }
+            };
+        }
+        img
+    }
 }
 
 impl From<Avatar> for RgbaImage {
     // Call the builder().build() method will convert Avatar into ImgBuffer
     fn from(avatar: Avatar) -> Self {
-        if !avatar.enable_crop {
-            return avatar.img_data;
-        }
+        let (filter, anchor, max_width) = (avatar.filter, avatar.crop_anchor, avatar.max_width);
+        let (zoom, offset, resize_filter) = (avatar.zoom, avatar.offset, avatar.resize_filter);
+        let buffer = pan_zoom(scale_avatar(avatar), zoom, offset, resize_filter);
+        let buffer = match max_width {
+            Some(max_width) if buffer.width() > max_width => {
+                let max_width = max_width.max(1);
+                let crop = buffer.width() - max_width;
+                let x = match anchor {
+                    CropAnchor::Left => 0,
+                    CropAnchor::Center => crop / 2,
+                    CropAnchor::Right => crop,
+                };
+                imageops::crop_imm(&buffer, x, 0, max_width, buffer.height()).to_image()
+            }
+            _ => buffer,
+        };
+        filter.apply(buffer)
+    }
+}
+
+// Scale the image up by `zoom`, then crop a window of the original size, centered and moved by
+// `offset`.
+fn pan_zoom(img: RgbaImage, zoom: f32, offset: (i32, i32), filter: FilterType) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    if zoom <= 1.0 && offset == (0, 0) || width == 0 || height == 0 {
+        return img;
+    }
+    let zoom = zoom.max(1.0);
+    let zoomed_w = ((width as f32 * zoom).round() as u32).max(width);
+    let zoomed_h = ((height as f32 * zoom).round() as u32).max(height);
+    let zoomed = imageops::resize(&img, zoomed_w, zoomed_h, filter);
+    let window = |zoomed: u32, size: u32, offset: i32| {
+        let max = (zoomed - size) as i64;
+        (max / 2 + offset as i64).clamp(0, max) as u32
+    };
+    let x = window(zoomed_w, width, offset.0);
+    let y = window(zoomed_h, height, offset.1);
+    imageops::crop_imm(&zoomed, x, y, width, height).to_image()
+}
+
+// Scale and crop the avatar to the background height, without the filter and the width limit.
+fn scale_avatar(avatar: Avatar) -> RgbaImage {
+    if let Some(region) = avatar.region {
+        let img_data = crop_to_rect(avatar.img_data, avatar.crop_rect);
+        return cover(&img_data, region, avatar.crop_anchor, avatar.resize_filter);
+    }
 
-        let ratio = avatar.img_data.width() / avatar.img_data.height();
-        let output_width = avatar.bg_height * ratio;
+    if !avatar.enable_crop {
+        return avatar.img_data;
+    }
 
-        // First let use scale the avatar to fit the background
-        let mut buffer = imageops::resize(
-            &avatar.img_data,
-            output_width,
+    if avatar.crop_rect.is_some() {
+        let region = crop_to_rect(avatar.img_data, avatar.crop_rect);
+        let (width, height) = region.dimensions();
+        let output_width = (width as f32 * avatar.bg_height as f32 / height as f32).round();
+        return imageops::resize(
+            &region,
+            (output_width as u32).max(1),
             avatar.bg_height,
-            FilterType::CatmullRom,
+            avatar.resize_filter,
         );
+    }
 
-        // Then crop 1/4 and keep 3/4 of the avatar, this can make some dramatic view effect for the
-        // final output image.
-        let crop = output_width / 4;
-        imageops::crop(&mut buffer, crop, 0, output_width - crop, avatar.bg_height).to_image()
+    // A tall image has no whole number ratio, it keeps the exact one with at least 1px of width
+    let (img_w, img_h) = avatar.img_data.dimensions();
+    let ratio = img_w / img_h;
+    let output_width = if avatar.keep_aspect_ratio || ratio == 0 {
+        ((img_w as f32 * avatar.bg_height as f32 / img_h as f32).round() as u32).max(1)
+    } else {
+        avatar.bg_height.saturating_mul(ratio)
+    };
+
+    // First let use scale the avatar to fit the background
+    let mut buffer = imageops::resize(
+        &avatar.img_data,
+        output_width,
+        avatar.bg_height,
+        avatar.resize_filter,
+    );
+
+    // Then crop 1/4 and keep 3/4 of the avatar, this can make some dramatic view effect for the
+    // final output image.
+    let crop = output_width / 4;
+    let x = match avatar.crop_anchor {
+        CropAnchor::Left => 0,
+        CropAnchor::Center => crop / 2,
+        CropAnchor::Right => crop,
+    };
+    imageops::crop(&mut buffer, x, 0, output_width - crop, avatar.bg_height).to_image()
+}
+
+// Keep the `rect` of the image. The rect is clamped to the image, and an empty one keeps the whole
+// image.
+fn crop_to_rect(img: RgbaImage, rect: Option<(u32, u32, u32, u32)>) -> RgbaImage {
+    let Some((x, y, width, height)) = rect else {
+        return img;
+    };
+    let (img_w, img_h) = img.dimensions();
+    let width = width.min(img_w.saturating_sub(x));
+    let height = height.min(img_h.saturating_sub(y));
+    if width == 0 || height == 0 {
+        return img;
     }
+    imageops::crop_imm(&img, x, y, width, height).to_image()
+}
+
+// Scale the image until it covers `width`x`height`, then crop the overflow. The horizontal crop
+// follows the anchor, and the vertical crop is centered.
+fn cover(
+    img: &RgbaImage,
+    (width, height): (u32, u32),
+    anchor: CropAnchor,
+    filter: FilterType,
+) -> RgbaImage {
+    let (img_w, img_h) = img.dimensions();
+    let scale = f32::max(width as f32 / img_w as f32, height as f32 / img_h as f32);
+    let (cover_w, cover_h) = (
+        ((img_w as f32 * scale).ceil() as u32).max(width),
+        ((img_h as f32 * scale).ceil() as u32).max(height),
+    );
+    let resized = imageops::resize(img, cover_w, cover_h, filter);
+    let x = match anchor {
+        CropAnchor::Left => 0,
+        CropAnchor::Center => (cover_w - width) / 2,
+        CropAnchor::Right => cover_w - width,
+    };
+    imageops::crop_imm(&resized, x, (cover_h - height) / 2, width, height).to_image()
 }
 
+/// A solid rule separating the avatar from the quote area.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BorderConfig {
+    pub width: u32,
+    pub color: Rgba<u8>,
+}
+
+/// Generate a Telegram style avatar: a letter on a colored circle.
 #[derive(TypedBuilder)]
 #[builder(build_method(into = RgbaImage))]
 pub struct TgAvatar<'a> {
     id: u64,
-    bg_dim: (u32, u32),
+    /// Size of the tile, like 1/3 of the background width and its full height for the avatar
+    /// region
+    size: (u32, u32),
     info: TextDrawInfo<'a>,
+    /// Stroke the circle with the given border
+    #[builder(default)]
+    border: Option<BorderConfig>,
+    /// Colors to pick the circle color from. Use the Telegram colors when empty.
+    #[builder(default)]
+    palette: &'a [[u8; 4]],
+    /// Use this color for the circle instead of picking one from the palette
+    #[builder(default)]
+    color: Option<[u8; 4]>,
+    /// The gap between circle and canvas edge, as a ratio of the canvas width
+    #[builder(default = 1.0 / 12.0)]
+    circle_padding_ratio: f32,
+    #[builder(default)]
+    shape: AvatarShape,
+    /// Shrink the letter so its height is at most this ratio of the circle radius, and its width
+    /// stays within the same ratio of the diameter. The scale of `info` is the upper bound, and
+    /// is used as is when `None`.
+    #[builder(default)]
+    letter_ratio: Option<f32>,
+}
+
+/// FNV-1a hash, which is stable across runs and platforms, unlike the std `DefaultHasher`.
+pub fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
 const COLOR: [[u8; 4]; 7] = [
@@ -57,36 +377,103 @@ const COLOR: [[u8; 4]; 7] = [
 
 impl<'a> From<TgAvatar<'a>> for RgbaImage {
     fn from(data: TgAvatar) -> Self {
-        let (bg_w, bg_h) = data.bg_dim;
-        let mut canvas = RgbaImage::new(bg_w / 3, bg_h);
+        let mut canvas = RgbaImage::new(data.size.0, data.size.1);
 
         // First draw a circle background
-        let avatar_color = Rgba::from(COLOR[data.id as usize % 7]);
+        let palette = if data.palette.is_empty() {
+            &COLOR[..]
+        } else {
+            data.palette
+        };
+        let avatar_color = Rgba::from(
+            data.color
+                .unwrap_or(palette[(data.id % palette.len() as u64) as usize]),
+        );
 
         let (cv_w, cv_h) = canvas.dimensions();
         let (cv_w, cv_h) = (cv_w as i32, cv_h as i32);
         let circle_center = (cv_w / 2, cv_h / 2);
-        // keep only 1/12 gaps between circle and canvas
-        let radius = cv_w / 2 - cv_w / 12;
-        imageproc::drawing::draw_filled_circle_mut(
-            &mut canvas,
-            circle_center,
-            radius,
-            avatar_color,
-        );
+        let gap = (cv_w as f32 * data.circle_padding_ratio) as i32;
+        let radius = (cv_w / 2 - gap).max(0);
+        let corner_radius = match data.shape {
+            AvatarShape::Circle => {
+                imageproc::drawing::draw_filled_circle_mut(
+                    &mut canvas,
+                    circle_center,
+                    radius,
+                    avatar_color,
+                );
+                if let Some(border) = data.border {
+                    for r in radius..radius + border.width as i32 {
+                        imageproc::drawing::draw_hollow_circle_mut(
+                            &mut canvas,
+                            circle_center,
+                            r,
+                            border.color,
+                        );
+                    }
+                }
+                None
+            }
+            AvatarShape::RoundedSquare { radius } => Some(radius),
+            AvatarShape::Square => Some(0),
+        };
+        if let Some(corner_radius) = corner_radius {
+            // The square covers the same area as the circle bounding box
+            let side = radius as u32 * 2 + 1;
+            let mut tile = RgbaImage::from_pixel(side, side, avatar_color);
+            round_corners(&mut tile, corner_radius);
+            let border_width = data.border.map_or(0, |border| border.width);
+            if let Some(border) = data.border {
+                let mut frame = RgbaImage::from_pixel(
+                    side + border_width * 2,
+                    side + border_width * 2,
+                    border.color,
+                );
+                round_corners(&mut frame, corner_radius + border_width);
+                imageops::overlay(&mut frame, &tile, border_width as i64, border_width as i64);
+                tile = frame;
+            }
+            let offset = border_width as i64 + radius as i64;
+            imageops::overlay(
+                &mut canvas,
+                &tile,
+                circle_center.0 as i64 - offset,
+                circle_center.1 as i64 - offset,
+            );
+        }
 
         // Then draw the letter
         let info = data.info;
         let letter = info.text().to_uppercase();
-        let (w, h) = imageproc::drawing::text_size(info.scale(), info.font(), &letter);
-        // Adjust the font to be drawn on the center
-        let (x, y) = (circle_center.0 - (w / 2), circle_center.1 - (h - h / 3));
+        let scale = match data.letter_ratio {
+            Some(ratio) => {
+                // Measure at a reference scale, the size grows linearly with the scale
+                let probe = Scale::uniform(100.0);
+                let (probe_w, _) = imageproc::drawing::text_size(probe, info.font(), &letter);
+                let (top, bottom) =
+                    ink_vertical_bounds(probe, info.font(), &letter).unwrap_or_default();
+                let target = radius as f32 * ratio;
+                let by_height = target / (bottom - top).max(1) as f32;
+                let by_width = 2.0 * target / probe_w.max(1) as f32;
+                Scale::uniform((100.0 * by_height.min(by_width)).min(info.scale().x))
+            }
+            None => info.scale(),
+        };
+        let (w, _) = imageproc::drawing::text_size(scale, info.font(), &letter);
+        // Put the middle of the glyph ink, rather than the line box, on the circle center
+        let (ink_top, ink_bottom) =
+            ink_vertical_bounds(scale, info.font(), &letter).unwrap_or_default();
+        let (x, y) = (
+            circle_center.0 - (w / 2),
+            circle_center.1 - (ink_top + ink_bottom) / 2,
+        );
         imageproc::drawing::draw_text_mut(
             &mut canvas,
             info.color(),
             x,
             y,
-            info.scale(),
+            scale,
             info.font(),
             &letter,
         );
@@ -94,3 +481,333 @@ impl<'a> From<TgAvatar<'a>> for RgbaImage {
         canvas
     }
 }
+
+#[test]
+fn test_grayscale_avatar_filter() {
+    let img_data = image::open("./assets/avatar.png").unwrap().into_rgba8();
+    let avatar: RgbaImage = Avatar::builder()
+        .img_data(img_data)
+        .bg_height(200)
+        .filter(AvatarFilter::Grayscale)
+        .build();
+
+    assert!(avatar.pixels().all(|p| p.0[0] == p.0[1] && p.0[1] == p.0[2]));
+}
+
+#[test]
+fn test_avatar_crop_anchor() {
+    // The left half is black and the right half is white
+    let img_data = RgbaImage::from_fn(800, 200, |x, _| {
+        if x < 400 {
+            Rgba([0, 0, 0, 255])
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    });
+    let crop = |anchor| -> RgbaImage {
+        Avatar::builder()
+            .img_data(img_data.clone())
+            .bg_height(200)
+            .crop_anchor(anchor)
+            .build()
+    };
+    // The black/white edge is at x = 400 of the scaled avatar, and 200px of it is cropped
+    let edge = |img: &RgbaImage| (0..img.width()).find(|x| img.get_pixel(*x, 100).0[0] > 127);
+
+    for (anchor, origin) in [
+        (CropAnchor::Left, 0),
+        (CropAnchor::Center, 100),
+        (CropAnchor::Right, 200),
+    ] {
+        let avatar = crop(anchor);
+        assert_eq!(avatar.width(), 600);
+        let edge = edge(&avatar).unwrap() as i32;
+        assert!(
+            (edge - (400 - origin)).abs() <= 2,
+            "{anchor:?}: edge at {edge}"
+        );
+    }
+}
+
+#[cfg(feature = "exif")]
+#[test]
+fn test_exif_orientation() {
+    // Red on the left and blue on the right, stored sideways
+    let img = RgbaImage::from_fn(40, 20, |x, _| {
+        if x < 20 {
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([0, 0, 255, 255])
+        }
+    });
+    let mut jpeg = Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgba8(img)
+        .into_rgb8()
+        .write_to(&mut jpeg, ImageFormat::Jpeg)
+        .unwrap();
+    let jpeg = jpeg.into_inner();
+
+    // APP1 segment with a big endian TIFF header and a single IFD entry: Orientation = 6
+    let mut app1 = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+    app1.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0]);
+    let mut buffer = jpeg[..2].to_vec();
+    buffer.extend_from_slice(&[0xFF, 0xE1]);
+    buffer.extend_from_slice(&(app1.len() as u16 + 2).to_be_bytes());
+    buffer.extend_from_slice(&app1);
+    buffer.extend_from_slice(&jpeg[2..]);
+
+    // Orientation 6 means the photo should be rotated 90 degrees clockwise to display
+    let avatar = decode_avatar(&buffer).unwrap();
+    assert_eq!(avatar.dimensions(), (20, 40));
+    let [r, _, b, _] = avatar.get_pixel(10, 5).0;
+    assert!(r > 200 && b < 50);
+    let [r, _, b, _] = avatar.get_pixel(10, 35).0;
+    assert!(r < 50 && b > 200);
+}
+
+#[test]
+fn test_custom_letter_avatar_palette() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let info = TextDrawInfo::builder()
+        .text("k")
+        .rgba([255, 255, 255, 255])
+        .scale(100.0)
+        .font(&font)
+        .build();
+    let palette = [[12, 34, 56, 255]];
+    let avatar: RgbaImage = TgAvatar::builder()
+        .id(13)
+        .size((300, 300))
+        .info(info)
+        .palette(&palette)
+        .build();
+
+    // Sample inside the circle but away from the letter
+    assert_eq!(avatar.get_pixel(150 - 100, 150).0, palette[0]);
+}
+
+#[test]
+fn test_letter_avatar_vertical_center() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    for scale in [120.0, 300.0] {
+        let info = TextDrawInfo::builder()
+            .text("k")
+            .rgba([255, 255, 255, 255])
+            .scale(scale)
+            .font(&font)
+            .build();
+        let avatar: RgbaImage = TgAvatar::builder()
+            .id(0)
+            .size((600, 600))
+            .info(info)
+            .build();
+
+        let rows = avatar
+            .enumerate_pixels()
+            // the circle is [255, 81, 106], so bright green channel means the letter
+            .filter(|(_, _, p)| p.0[1] > 200)
+            .map(|(_, y, _)| y);
+        let (top, bottom) = rows.fold((u32::MAX, 0), |(t, b), y| (t.min(y), b.max(y)));
+        let center = (top + bottom) as i32 / 2;
+        assert!(
+            (center - 300).abs() <= 2,
+            "scale {scale}: center at {center}"
+        );
+    }
+}
+
+#[test]
+fn test_letter_avatar_circle_padding() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let diameter = |ratio: f32| {
+        let info = TextDrawInfo::builder()
+            .text("")
+            .rgba([255, 255, 255, 255])
+            .scale(100.0)
+            .font(&font)
+            .build();
+        let avatar: RgbaImage = TgAvatar::builder()
+            .id(0)
+            .size((240, 300))
+            .info(info)
+            .circle_padding_ratio(ratio)
+            .build();
+        (0..avatar.width())
+            .filter(|x| avatar.get_pixel(*x, 150).0 == COLOR[0])
+            .count() as i32
+    };
+
+    // canvas is 240px wide
+    assert!((diameter(0.0) - 240).abs() <= 2);
+    assert!((diameter(0.25) - 120).abs() <= 2);
+}
+
+#[test]
+fn test_square_letter_avatar() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Bold.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let info = TextDrawInfo::builder()
+        .text("A")
+        .rgba([255, 255, 255, 255])
+        .scale(100.0)
+        .font(&font)
+        .build();
+    let avatar: RgbaImage = TgAvatar::builder()
+        .id(0)
+        .info(info)
+        .size((200, 200))
+        .circle_padding_ratio(0.0)
+        .shape(AvatarShape::Square)
+        .build();
+
+    // The circle would leave these corners transparent
+    for (x, y) in [(0, 0), (199, 0), (0, 199), (199, 199)] {
+        assert_eq!(avatar.get_pixel(x, y).0, COLOR[0], "({x}, {y})");
+    }
+}
+
+#[test]
+fn test_letter_fits_small_circle() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Bold.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    for text in ["W", "g", "JD"] {
+        // The scale is far too large for the 48px avatar, the ratio shrinks it
+        let info = TextDrawInfo::builder()
+            .text(text)
+            .rgba([255, 255, 255, 255])
+            .scale(300.0)
+            .font(&font)
+            .build();
+        let avatar: RgbaImage = TgAvatar::builder()
+            .id(0)
+            .info(info)
+            .size((48, 48))
+            .letter_ratio(Some(0.8))
+            .build();
+
+        // Circle center is (24, 24) with radius 20
+        let letter: Vec<(u32, u32)> = avatar
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p.0 == [255, 255, 255, 255])
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!letter.is_empty(), "{text}");
+        for (x, y) in letter {
+            let (dx, dy) = (x as f32 - 24.0, y as f32 - 24.0);
+            assert!((dx * dx + dy * dy).sqrt() < 20.0, "{text}: ({x}, {y})");
+        }
+    }
+
+    // A letter which already fits keeps the scale of the caller, with or without the ratio
+    let letter_rows = |letter_ratio| {
+        let info = TextDrawInfo::builder()
+            .text("W")
+            .rgba([255, 255, 255, 255])
+            .scale(20.0)
+            .font(&font)
+            .build();
+        let avatar: RgbaImage = TgAvatar::builder()
+            .id(0)
+            .info(info)
+            .size((300, 300))
+            .letter_ratio(letter_ratio)
+            .build();
+        let rows = avatar
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p.0 == [255, 255, 255, 255])
+            .map(|(_, y, _)| y);
+        let (top, bottom) = rows.fold((u32::MAX, 0), |(t, b), y| (t.min(y), b.max(y)));
+        bottom - top + 1
+    };
+    assert_eq!(letter_rows(None), letter_rows(Some(0.8)));
+    assert!(letter_rows(None) < 20);
+}
+
+#[test]
+fn test_avatar_max_width() {
+    // Uncropped images are kept as is, but the width limit still applies
+    let img_data = RgbaImage::from_fn(5000, 300, |x, _| Rgba([(x % 256) as u8, 0, 0, 255]));
+    let avatar: RgbaImage = Avatar::builder()
+        .img_data(img_data)
+        .bg_height(300)
+        .enable_crop(false)
+        .crop_anchor(CropAnchor::Left)
+        .max_width(Some(450))
+        .build();
+    assert_eq!(avatar.dimensions(), (450, 300));
+    assert_eq!(avatar.get_pixel(10, 0).0[0], 10);
+
+    // A tall image keeps its aspect ratio instead of collapsing to 0px wide
+    let avatar: RgbaImage = Avatar::builder()
+        .img_data(RgbaImage::new(300, 900))
+        .bg_height(300)
+        .build();
+    assert_eq!(avatar.dimensions(), (75, 300));
+}
+
+#[test]
+fn test_avatar_aspect_ratio() {
+    let avatar = |keep_aspect_ratio| -> RgbaImage {
+        Avatar::builder()
+            .img_data(RgbaImage::new(400, 300))
+            .bg_height(300)
+            .keep_aspect_ratio(keep_aspect_ratio)
+            .build()
+    };
+    // The whole number ratio is 1, 3/4 of the 300px square is kept
+    assert_eq!(avatar(false).dimensions(), (225, 300));
+    assert_eq!(avatar(true).dimensions(), (300, 300));
+}
+
+#[test]
+fn test_avatar_crop_rect_clamped() {
+    let avatar = |crop_rect| -> RgbaImage {
+        Avatar::builder()
+            .img_data(RgbaImage::new(400, 300))
+            .bg_height(300)
+            .crop_rect(Some(crop_rect))
+            .build()
+    };
+    // Only the 100x300 part inside the image is kept
+    assert_eq!(avatar((300, 0, 200, 300)).dimensions(), (100, 300));
+    // Nothing is left, so the whole image is scaled instead
+    assert_eq!(avatar((0, 500, 100, 100)).dimensions(), (400, 300));
+    assert_eq!(avatar((0, 0, 0, 100)).dimensions(), (400, 300));
+}
+
+#[test]
+fn test_avatar_zoom() {
+    // Each quarter of the image has its own color
+    let img_data = RgbaImage::from_fn(400, 400, |x, y| match (x < 200, y < 200) {
+        (true, true) => Rgba([255, 0, 0, 255]),
+        (false, true) => Rgba([0, 255, 0, 255]),
+        (true, false) => Rgba([0, 0, 255, 255]),
+        (false, false) => Rgba([255, 255, 255, 255]),
+    });
+    let zoomed = |zoom, offset| -> RgbaImage {
+        Avatar::builder()
+            .img_data(img_data.clone())
+            .bg_height(400)
+            .enable_crop(false)
+            .zoom(zoom)
+            .offset(offset)
+            .build()
+    };
+
+    // The center is magnified, so the quarters reach further from the middle
+    let avatar = zoomed(1.5, (0, 0));
+    assert_eq!(avatar.dimensions(), (400, 400));
+    assert_eq!(avatar.get_pixel(150, 150).0, [255, 0, 0, 255]);
+    assert_eq!(avatar.get_pixel(250, 250).0, [255, 255, 255, 255]);
+    // 1/6 of the image is cut from each side
+    assert_eq!(avatar.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    assert_eq!(zoomed(1.0, (0, 0)).get_pixel(150, 150).0, [255, 0, 0, 255]);
+
+    // Pan to the right edge, the offset can't go past it
+    let avatar = zoomed(1.5, (1000, 0));
+    assert_eq!(avatar.get_pixel(399, 300).0, [255, 255, 255, 255]);
+    assert_eq!(avatar.get_pixel(150, 100).0, [0, 255, 0, 255]);
+}
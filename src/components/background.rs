@@ -19,3 +19,92 @@ impl From<Background> for RgbaImage {
         RgbaImage::from_pixel(width, height, bg.color)
     }
 }
+
+/// Blend the image onto an opaque `color`, so the translucent pixels show that color instead of
+/// whatever the image is composited on later.
+pub fn flatten(img: &mut RgbaImage, color: Rgba<u8>) {
+    for pixel in img.pixels_mut() {
+        let alpha = pixel.0[3] as u32;
+        for (channel, bg) in pixel.0[..3].iter_mut().zip(color.0) {
+            *channel = ((*channel as u32 * alpha + bg as u32 * (255 - alpha) + 127) / 255) as u8;
+        }
+        pixel.0[3] = 255;
+    }
+}
+
+/// Make the pixels outside the rounded rectangle with the given corner radius transparent. The
+/// radius is clamped to half of the shorter side, and the arc edges are anti-aliased.
+pub fn round_corners(img: &mut RgbaImage, radius: u32) {
+    let (width, height) = img.dimensions();
+    let radius = radius.min(width / 2).min(height / 2);
+    let r = radius as f32;
+    for y in 0..height {
+        for x in 0..width {
+            // Distance into the corner square, measured from the arc center
+            let dx = r - x.min(width - 1 - x) as f32 - 0.5;
+            let dy = r - y.min(height - 1 - y) as f32 - 0.5;
+            if dx <= 0.0 || dy <= 0.0 {
+                continue;
+            }
+            let coverage = (r - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+            let alpha = &mut img.get_pixel_mut(x, y).0[3];
+            *alpha = (*alpha as f32 * coverage).round() as u8;
+        }
+    }
+}
+
+/// Crop the image to the bounding box of its non-transparent pixels. A fully transparent image
+/// is returned as is.
+pub fn trim(img: RgbaImage) -> RgbaImage {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel.0[3] == 0 {
+            continue;
+        }
+        let (left, top, right, bottom) = bounds.get_or_insert((x, y, x, y));
+        *left = (*left).min(x);
+        *top = (*top).min(y);
+        *right = (*right).max(x);
+        *bottom = (*bottom).max(y);
+    }
+    match bounds {
+        Some((left, top, right, bottom)) => {
+            image::imageops::crop_imm(&img, left, top, right - left + 1, bottom - top + 1)
+                .to_image()
+        }
+        None => img,
+    }
+}
+
+#[test]
+fn test_round_corners() {
+    let mut img = RgbaImage::from_pixel(200, 100, Rgba([255, 255, 255, 255]));
+    round_corners(&mut img, 20);
+    for (x, y) in [(0, 0), (199, 0), (0, 99), (199, 99), (3, 3)] {
+        assert_eq!(img.get_pixel(x, y).0[3], 0, "({x}, {y})");
+    }
+    for (x, y) in [(100, 0), (0, 50), (20, 20), (100, 50)] {
+        assert_eq!(img.get_pixel(x, y).0[3], 255, "({x}, {y})");
+    }
+}
+
+#[test]
+fn test_flatten() {
+    let mut img = RgbaImage::from_fn(3, 1, |x, _| Rgba([0, 0, 0, [0, 128, 255][x as usize]]));
+    flatten(&mut img, Rgba([255, 255, 255, 255]));
+    assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255, 255]);
+    assert_eq!(img.get_pixel(1, 0).0, [127, 127, 127, 255]);
+    assert_eq!(img.get_pixel(2, 0).0, [0, 0, 0, 255]);
+}
+
+#[test]
+fn test_trim() {
+    let mut img = RgbaImage::new(100, 50);
+    img.put_pixel(10, 20, Rgba([255, 255, 255, 1]));
+    img.put_pixel(60, 30, Rgba([255, 0, 0, 255]));
+    let img = trim(img);
+    assert_eq!(img.dimensions(), (51, 11));
+    assert_eq!(img.get_pixel(50, 10).0, [255, 0, 0, 255]);
+
+    assert_eq!(trim(RgbaImage::new(4, 4)).dimensions(), (4, 4));
+}
@@ -0,0 +1,55 @@
+use image::{imageops, imageops::FilterType, RgbaImage};
+use typed_builder::TypedBuilder;
+
+/// A component for creating a heavily blurred copy of the avatar that covers the whole avatar
+/// region. It is used as backdrop when the avatar itself is too narrow to fill the region.
+#[derive(TypedBuilder)]
+#[builder(build_method(into = RgbaImage))]
+pub struct BlurFill<'a> {
+    img_data: &'a RgbaImage,
+    region: (u32, u32),
+    #[builder(default = 8.0)]
+    sigma: f32,
+}
+
+impl<'a> From<BlurFill<'a>> for RgbaImage {
+    fn from(fill: BlurFill<'a>) -> Self {
+        let (region_w, region_h) = fill.region;
+        let (img_w, img_h) = fill.img_data.dimensions();
+
+        // Blurring at full size is slow, so we blur a 1/4 sized copy then scale it back up.
+        let (small_w, small_h) = ((region_w / 4).max(1), (region_h / 4).max(1));
+
+        // Cover-fit: scale the image until both sides reach the region, then crop from center.
+        let scale = f32::max(
+            small_w as f32 / img_w as f32,
+            small_h as f32 / img_h as f32,
+        );
+        let (cover_w, cover_h) = (
+            ((img_w as f32 * scale).ceil() as u32).max(small_w),
+            ((img_h as f32 * scale).ceil() as u32).max(small_h),
+        );
+        let mut cover = imageops::resize(fill.img_data, cover_w, cover_h, FilterType::Triangle);
+        let cover = imageops::crop(
+            &mut cover,
+            (cover_w - small_w) / 2,
+            (cover_h - small_h) / 2,
+            small_w,
+            small_h,
+        )
+        .to_image();
+
+        let blurred = imageops::blur(&cover, fill.sigma);
+        imageops::resize(&blurred, region_w, region_h, FilterType::Triangle)
+    }
+}
+
+#[test]
+fn test_blur_fill_golden() {
+    let img_data = image::open("./assets/avatar.png").unwrap().into_rgba8();
+    let fill: RgbaImage = BlurFill::builder()
+        .img_data(&img_data)
+        .region((120, 240))
+        .build();
+    crate::testing::assert_golden("blur_fill", &fill);
+}
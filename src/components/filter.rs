@@ -0,0 +1,36 @@
+use super::AvatarFilter;
+use image::RgbaImage;
+
+/// Color filter applied to the whole image after the text is drawn.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputFilter {
+    Grayscale,
+    Sepia,
+    /// Invert the color channels, the alpha channel is kept.
+    Invert,
+}
+
+impl OutputFilter {
+    pub fn apply(self, img: RgbaImage) -> RgbaImage {
+        match self {
+            OutputFilter::Grayscale => AvatarFilter::Grayscale.apply(img),
+            OutputFilter::Sepia => AvatarFilter::Sepia.apply(img),
+            OutputFilter::Invert => {
+                let mut img = img;
+                for pixel in img.pixels_mut() {
+                    for channel in &mut pixel.0[..3] {
+                        *channel = 255 - *channel;
+                    }
+                }
+                img
+            }
+        }
+    }
+}
+
+#[test]
+fn test_invert_filter() {
+    let img = RgbaImage::from_pixel(2, 2, image::Rgba([10, 200, 255, 128]));
+    let img = OutputFilter::Invert.apply(img);
+    assert_eq!(img.get_pixel(1, 1).0, [245, 55, 0, 128]);
+}
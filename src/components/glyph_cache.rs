@@ -0,0 +1,188 @@
+use rusttype::{Font, GlyphId, Rect, Scale};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Clone, Copy)]
+struct GlyphMetrics {
+    id: GlyphId,
+    advance: f32,
+    // Relative to the glyph origin, `None` for glyphs without ink like space
+    bounds: Option<Rect<f32>>,
+}
+
+/// Memoized glyph metrics keyed by codepoint and scale, so measuring text doesn't need to look up
+/// the font tables for every char again. The font is not part of the key, so one cache should
+/// only serve one font.
+///
+/// The cache keeps at most `capacity` glyphs, and starts over once it is full. A cache with zero
+/// capacity is disabled.
+pub struct GlyphCache {
+    capacity: usize,
+    table: Mutex<HashMap<(char, u32, u32), GlyphMetrics>>,
+}
+
+impl Default for GlyphCache {
+    /// Enough for the common CJK chars at a few scales
+    fn default() -> Self {
+        Self::new(8192)
+    }
+}
+
+impl GlyphCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            table: Mutex::default(),
+        }
+    }
+
+    /// How many glyphs are in the cache.
+    pub fn len(&self) -> usize {
+        self.table.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hold the cache while measuring, so every char doesn't take the lock again.
+    pub(crate) fn lock(&self) -> Glyphs<'_> {
+        Glyphs {
+            capacity: self.capacity,
+            table: self.table.lock().unwrap(),
+        }
+    }
+}
+
+/// The locked table of a [`GlyphCache`].
+pub(crate) struct Glyphs<'a> {
+    capacity: usize,
+    table: MutexGuard<'a, HashMap<(char, u32, u32), GlyphMetrics>>,
+}
+
+impl Glyphs<'_> {
+    fn metrics(&mut self, font: &Font<'_>, scale: Scale, c: char) -> GlyphMetrics {
+        let key = (c, scale.x.to_bits(), scale.y.to_bits());
+        if let Some(metrics) = self.table.get(&key) {
+            return *metrics;
+        }
+        let glyph = font.glyph(c).scaled(scale);
+        let metrics = GlyphMetrics {
+            id: glyph.id(),
+            advance: glyph.h_metrics().advance_width,
+            bounds: glyph.exact_bounding_box(),
+        };
+        if self.capacity > 0 {
+            if self.table.len() >= self.capacity {
+                self.table.clear();
+            }
+            self.table.insert(key, metrics);
+        }
+        metrics
+    }
+}
+
+/// Measure a line of text one char at a time. After each push, the size is the same as what
+/// `imageproc::drawing::text_size` returns for all the chars pushed so far.
+pub(crate) struct LineMeasure<'a> {
+    font: &'a Font<'a>,
+    scale: Scale,
+    ascent: f32,
+    caret: f32,
+    last_glyph: Option<GlyphId>,
+    size: (i32, i32),
+}
+
+impl<'a> LineMeasure<'a> {
+    pub(crate) fn new(font: &'a Font<'a>, scale: Scale) -> Self {
+        Self {
+            font,
+            scale,
+            ascent: font.v_metrics(scale).ascent,
+            caret: 0.0,
+            last_glyph: None,
+            size: (0, 0),
+        }
+    }
+
+    /// Start measuring a new line.
+    pub(crate) fn clear(&mut self) {
+        self.caret = 0.0;
+        self.last_glyph = None;
+        self.size = (0, 0);
+    }
+
+    /// Append the char measured with `font` to the line and return the new line size. Switching
+    /// the font starts a new run, which is drawn from the pen position rounded to a whole pixel
+    /// and isn't kerned with the chars before it.
+    pub(crate) fn push(
+        &mut self,
+        glyphs: &mut Glyphs<'_>,
+        font: &'a Font<'a>,
+        c: char,
+    ) -> (i32, i32) {
+        if !std::ptr::eq(self.font, font) {
+            self.font = font;
+            self.ascent = font.v_metrics(self.scale).ascent;
+            self.caret = self.caret.round();
+            self.last_glyph = None;
+        }
+        let glyph = glyphs.metrics(self.font, self.scale, c);
+        if let Some(last) = self.last_glyph {
+            self.caret += self.font.pair_kerning(self.scale, last, glyph.id);
+        }
+        // Round to the pixel grid the same way as rusttype does for a glyph at this position
+        if let Some(bounds) = glyph.bounds {
+            let pixel_max =
+                |pos: f32, max: f32| pos.trunc() as i32 + (max + pos.fract()).ceil() as i32;
+            self.size = (
+                self.size.0.max(pixel_max(self.caret, bounds.max.x)),
+                self.size.1.max(pixel_max(self.ascent, bounds.max.y)),
+            );
+        }
+        self.caret += glyph.advance;
+        self.last_glyph = Some(glyph.id);
+        self.size
+    }
+}
+
+#[test]
+fn test_line_measure_matches_text_size() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = Font::try_from_bytes(&font).unwrap();
+    let cache = GlyphCache::default();
+    let mut glyphs = cache.lock();
+    for scale in [13.7, 60.0, 140.0] {
+        let scale = Scale::uniform(scale);
+        let mut measure = LineMeasure::new(&font, scale);
+        let text = "AVATAR To, 大家好！ fi Wa\n";
+        for (i, c) in text.char_indices() {
+            let size = measure.push(&mut glyphs, &font, c);
+            let expect = imageproc::drawing::text_size(scale, &font, &text[..i + c.len_utf8()]);
+            assert_eq!(size, expect);
+        }
+    }
+}
+
+#[test]
+fn test_glyph_cache_capacity() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = Font::try_from_bytes(&font).unwrap();
+    let text = "AVATAR To, 大家好！";
+    let measure = |cache: &GlyphCache| {
+        let mut glyphs = cache.lock();
+        let mut measure = LineMeasure::new(&font, Scale::uniform(40.0));
+        text.chars()
+            .fold((0, 0), |_, c| measure.push(&mut glyphs, &font, c))
+    };
+
+    let (bounded, disabled) = (GlyphCache::new(4), GlyphCache::new(0));
+    let size = measure(&bounded);
+    assert!(bounded.len() <= 4);
+    assert_eq!(measure(&disabled), size);
+    assert!(disabled.is_empty());
+    assert_eq!(
+        size,
+        imageproc::drawing::text_size(Scale::uniform(40.0), &font, text)
+    );
+}
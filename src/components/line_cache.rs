@@ -0,0 +1,97 @@
+use super::{Lines, TextDrawInfo};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// Text, scale x and y, width limit and letter spacing. Floats are compared by their bits.
+type Key = (String, u32, u32, i32, u32);
+
+/// A least recently used cache of wrapped [`Lines`], so rendering the same quote again skips the
+/// measurement. The font is not part of the key, so one cache should only serve one font.
+///
+/// A cache with zero capacity is disabled and never measures anything itself.
+#[derive(Default)]
+pub struct LineCache {
+    capacity: usize,
+    // The most recently used entry is at the back
+    entries: Mutex<VecDeque<(Key, Lines)>>,
+    measured: AtomicUsize,
+}
+
+impl LineCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    /// Get the wrapped lines from the cache, or wrap the text and remember the result.
+    pub fn get_or_wrap(&self, info: &TextDrawInfo<'_>, limit: i32) -> Lines {
+        if self.capacity == 0 {
+            return Lines::new(info, limit);
+        }
+
+        let scale = info.scale();
+        let key = (
+            info.text().to_string(),
+            scale.x.to_bits(),
+            scale.y.to_bits(),
+            limit,
+            info.letter_spacing().to_bits(),
+        );
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(pos) = entries.iter().position(|(k, _)| *k == key) {
+                let entry = entries.remove(pos).unwrap();
+                let lines = entry.1.clone();
+                entries.push_back(entry);
+                return lines;
+            }
+        }
+
+        // Measure without holding the lock, other threads may use the cache meanwhile
+        self.measured.fetch_add(1, Ordering::Relaxed);
+        let lines = Lines::new(info, limit);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back((key, lines.clone()));
+        lines
+    }
+
+    /// How many times the text was measured because it was not in the cache.
+    pub fn measured(&self) -> usize {
+        self.measured.load(Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn test_line_cache_eviction() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let info = |text| {
+        TextDrawInfo::builder()
+            .text(text)
+            .rgba([255, 255, 255, 255])
+            .scale(40.0)
+            .font(&font)
+            .build()
+    };
+    let cache = LineCache::new(2);
+
+    cache.get_or_wrap(&info("a"), 1000);
+    cache.get_or_wrap(&info("b"), 1000);
+    cache.get_or_wrap(&info("a"), 1000);
+    assert_eq!(cache.measured(), 2);
+    // "b" is the least recently used one now
+    cache.get_or_wrap(&info("c"), 1000);
+    cache.get_or_wrap(&info("a"), 1000);
+    assert_eq!(cache.measured(), 3);
+    cache.get_or_wrap(&info("b"), 1000);
+    assert_eq!(cache.measured(), 4);
+    // A different width limit is another entry
+    cache.get_or_wrap(&info("b"), 500);
+    assert_eq!(cache.measured(), 5);
+}
@@ -1,13 +1,32 @@
+//! Building blocks used by [`QuoteProducer::make_image`](crate::QuoteProducer::make_image). The
+//! crate root re-exports the ones meant for custom layouts.
+
 mod avatar;
 mod background;
+mod blur_fill;
+mod filter;
+mod glyph_cache;
+mod line_cache;
 mod quotes;
+mod shadow;
 mod text;
 mod transition;
 
 pub use {
-    avatar::{Avatar, TgAvatar},
-    background::Background,
-    quotes::Quotes,
-    text::{Lines, TextDrawInfo},
-    transition::Transition,
+    avatar::{
+        decode_avatar, stable_hash, Avatar, AvatarFilter, AvatarShape, BorderConfig, CropAnchor,
+        TgAvatar,
+    },
+    background::{flatten, round_corners, trim, Background},
+    blur_fill::BlurFill,
+    filter::OutputFilter,
+    glyph_cache::GlyphCache,
+    line_cache::LineCache,
+    quotes::{ChipConfig, Horizontal, PanelConfig, QuoteReport, QuoteVertical, Quotes, Vertical},
+    shadow::{drop_shadow, DropShadow},
+    text::{
+        advance_width, initials, ink_vertical_bounds, normalize_text, parse_markdown, wrap_text,
+        CharStyle, Emphasis, Line, Lines, TextDrawInfo,
+    },
+    transition::{dither, feather, GradientDirection, Transition},
 };
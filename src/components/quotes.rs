@@ -1,7 +1,13 @@
-use super::{Lines, TextDrawInfo};
-use image::RgbaImage;
+use super::{
+    advance_width, ink_vertical_bounds, round_corners, CharStyle, Line, LineCache, Lines,
+    TextDrawInfo,
+};
+use image::{imageops, imageops::FilterType, Rgba, RgbaImage};
+use std::cell::Cell;
 use typed_builder::TypedBuilder;
 
+/// Draw the quote and the username on a transparent canvas that covers the area right to the
+/// avatar.
 #[derive(TypedBuilder)]
 #[builder(build_method(into = RgbaImage))]
 pub struct Quotes<'a> {
@@ -12,6 +18,146 @@ pub struct Quotes<'a> {
 
     quote_info: TextDrawInfo<'a>,
     user_info: TextDrawInfo<'a>,
+    /// Drawn right below the username, usually a dimmer `@handle`
+    #[builder(default)]
+    handle_info: Option<TextDrawInfo<'a>>,
+    /// Fake a bold weight by drawing the quote multiple times with 1px horizontal offsets
+    #[builder(default = false)]
+    faux_bold: bool,
+    /// Style of each char in the quote text, chars without a style here use the quote color and
+    /// font. Lines are wrapped with the font each char is drawn with.
+    #[builder(default)]
+    quote_styles: &'a [CharStyle<'a>],
+    /// Reuse the wrapped quote lines from this cache
+    #[builder(default, setter(strip_option))]
+    line_cache: Option<&'a LineCache>,
+    /// Write how the quote is laid out into this cell
+    #[builder(default)]
+    report: Option<&'a Cell<QuoteReport>>,
+    #[builder(default)]
+    username_position: Vertical,
+    #[builder(default)]
+    username_align: Horizontal,
+    /// Cut the quote to this many lines, ending with an ellipsis
+    #[builder(default)]
+    max_lines: Option<usize>,
+    /// Ends the last line of a quote cut by `max_lines`
+    #[builder(default = "\u{2026}")]
+    ellipsis: &'a str,
+    #[builder(default)]
+    quote_vertical: QuoteVertical,
+    /// Smaller text drawn between the quote and the username, wrapped like the quote
+    #[builder(default)]
+    subtitle_info: Option<TextDrawInfo<'a>>,
+    /// Draw a rounded panel behind the quote lines, to keep them readable over busy backgrounds
+    #[builder(default)]
+    text_panel: Option<PanelConfig>,
+    /// Move the CJK comma and full stop marks down by this ratio of the quote font size
+    #[builder(default)]
+    punctuation_offset: Option<f32>,
+    /// Put the username on a rounded colored chip, like a role badge
+    #[builder(default)]
+    username_chip: Option<ChipConfig>,
+    /// Drawn right before the first username line, scaled to the cap height of the username.
+    /// Handy for verified badges or platform icons.
+    #[builder(default)]
+    username_icon: Option<&'a RgbaImage>,
+    /// Fill the quote glyphs with a vertical gradient from the first color at the top of the
+    /// quote to the second at the bottom. The glyph colors are multiplied by it, so white text
+    /// takes the gradient as is.
+    #[builder(default)]
+    quote_gradient: Option<(Rgba<u8>, Rgba<u8>)>,
+    /// Only lay out the text and write the `report`, without drawing. The image is empty.
+    #[builder(default)]
+    measure_only: bool,
+}
+
+/// A rounded rectangle drawn behind the quote text.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PanelConfig {
+    /// Fill of the panel, its alpha sets how much of the background shows through
+    pub color: Rgba<u8>,
+    pub corner_radius: u32,
+    /// Space between the ink of the quote and the panel edges
+    pub padding: u32,
+}
+
+/// A rounded badge drawn behind the username.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChipConfig {
+    pub color: Rgba<u8>,
+    /// Color of the username on the chip, replacing its own color
+    pub text_color: Rgba<u8>,
+    /// Space between the username lines and the chip edges
+    pub padding: u32,
+    pub corner_radius: u32,
+}
+
+/// Where the quote block is placed vertically.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QuoteVertical {
+    /// Put the quote next to the middle line, on the opposite side of the username.
+    #[default]
+    Center,
+    /// Start the first line at this many pixels from the top.
+    FromTop(u32),
+    /// End the last line at this many pixels from the bottom.
+    FromBottom(u32),
+}
+
+/// Horizontal alignment of a text block.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Horizontal {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+/// Vertical position of a text block.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Vertical {
+    Top,
+    #[default]
+    Bottom,
+}
+
+/// How the quote text is laid out.
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct QuoteReport {
+    /// How many lines the quote is wrapped into
+    pub line_count: usize,
+    /// The quote is too tall and part of it is drawn outside of the image or over the username,
+    /// or the username block itself doesn't fit in the image
+    pub overflowed: bool,
+    /// Chars in the quote, the username, the handle or the subtitle that their font has no glyph
+    /// for, they are drawn as the `.notdef` box. Each char is listed once.
+    pub missing_glyphs: Vec<char>,
+}
+
+impl Quotes<'_> {
+    fn missing_glyphs(&self) -> Vec<char> {
+        let quote = self.quote_info.text().chars().enumerate().map(|(i, c)| {
+            let font = self
+                .quote_styles
+                .get(i)
+                .map_or(self.quote_info.font(), |style| style.font);
+            (c, font)
+        });
+        let attribution = std::iter::once(&self.user_info)
+            .chain(self.handle_info.as_ref())
+            .chain(self.subtitle_info.as_ref())
+            .flat_map(|info| info.text().chars().map(|c| (c, info.font())));
+
+        let mut missing = Vec::new();
+        for (c, font) in quote.chain(attribution) {
+            // Whitespace like `\n` is never drawn, so it doesn't matter if there is a glyph
+            if !c.is_whitespace() && font.glyph(c).id().0 == 0 && !missing.contains(&c) {
+                missing.push(c);
+            }
+        }
+        missing
+    }
 }
 
 //                                                          The X
@@ -19,57 +165,837 @@ pub struct Quotes<'a> {
 // <- - half width          ->|
 // <-        + other factor    ->|
 // <-    - w / 2  ->|
+//
+// The free space is split with one rounding, so the right margin is never smaller than the left
+// one, and is at most 1px larger.
 fn centered_text_x(bg_w: u32, text_w: i32, other_factor: u32) -> i32 {
-    (bg_w as i32 / 2) + (other_factor as i32) - (text_w / 2)
+    (bg_w as i32 - text_w).div_euclid(2) + (other_factor as i32)
+}
+
+// Left and right aligned text keep a gap from the canvas edges, and the left side is shifted away
+// from the avatar like centered text.
+fn aligned_text_x(canvas_w: u32, text_w: i32, shift: u32, gap: u32, align: Horizontal) -> i32 {
+    match align {
+        Horizontal::Left => (gap + shift) as i32,
+        Horizontal::Center => centered_text_x(canvas_w, text_w, shift),
+        Horizontal::Right => canvas_w as i32 - gap as i32 - text_w,
+    }
+}
+
+// Center the ink of the line, then move it left by the left side bearing of the first glyph, so
+// the ink starts right at the centered position. `overhang` is the ink drawn right to the last
+// glyph, like the extra passes of the faux bold.
+fn quote_line_x(canvas_w: u32, line: &Line, other_factor: u32, overhang: i32) -> i32 {
+    centered_text_x(canvas_w, line.ink_width() + overhang, other_factor) - line.first_char_bearing
+}
+
+// The marks that sit low in the em box, next to full-width chars they look too high.
+fn is_low_punctuation(c: char) -> bool {
+    matches!(c, '\u{3001}' | '\u{3002}' | '\u{FF0C}' | '\u{FF0E}')
+}
+
+// Split the line into runs of the same style, with the x offset of each run from the line start.
+// With letter spacing, every char is a run so they can be drawn apart from each other. The low
+// punctuation marks get their own runs if they are moved.
+fn style_runs<'l, 'a>(
+    line: &'l Line,
+    styles: &[CharStyle<'a>],
+    info: &'a TextDrawInfo<'a>,
+    split_punctuation: bool,
+) -> Vec<(&'l str, i32, CharStyle<'a>)> {
+    let default_style = CharStyle {
+        color: info.color(),
+        font: info.font(),
+    };
+    let mut runs = Vec::new();
+    let spacing = info.letter_spacing();
+    let mut offset: f32 = 0.0;
+    let mut run: Option<(usize, CharStyle)> = None;
+    let mut push_run = |start: usize, end: usize, style: CharStyle<'a>| {
+        let text = &line.text[start..end];
+        runs.push((text, offset.round() as i32, style));
+        offset += advance_width(info.scale(), style.font, text) as f32 + spacing;
+    };
+    let mut split_last = false;
+    for (i, (byte_idx, c)) in line.text.char_indices().enumerate() {
+        let style = styles.get(line.start + i).copied().unwrap_or(default_style);
+        let split = split_punctuation && is_low_punctuation(c);
+        match run {
+            Some((_, run_style))
+                if run_style == style && spacing == 0.0 && !split && !split_last => {}
+            Some((start, run_style)) => {
+                push_run(start, byte_idx, run_style);
+                run = Some((byte_idx, style));
+            }
+            None => run = Some((byte_idx, style)),
+        }
+        split_last = split;
+    }
+    if let Some((start, style)) = run {
+        push_run(start, line.text.len(), style);
+    }
+    runs
+}
+
+// Cover the ink of all the lines, which start at `y`, with the padding on each side.
+fn draw_panel(canvas: &mut RgbaImage, panel: &PanelConfig, lines: &Lines, shift: u32, y: i32) {
+    let Some((left, right)) = lines
+        .iter()
+        .filter(|line| line.ink_width() > 0)
+        .map(|line| {
+            let x = centered_text_x(canvas.width(), line.ink_width(), shift);
+            (x, x + line.ink_width())
+        })
+        .reduce(|(l1, r1), (l2, r2)| (l1.min(l2), r1.max(r2)))
+    else {
+        return;
+    };
+    let bounds = (left, y, right, y + lines.size().1);
+    fill_rounded_rect(
+        canvas,
+        bounds,
+        panel.padding,
+        panel.corner_radius,
+        panel.color,
+    );
+}
+
+// Blend a rounded rectangle covering `(left, top, right, bottom)` and the padding around it.
+fn fill_rounded_rect(
+    canvas: &mut RgbaImage,
+    (left, top, right, bottom): (i32, i32, i32, i32),
+    padding: u32,
+    corner_radius: u32,
+    color: Rgba<u8>,
+) {
+    let width = (right - left).max(0) as u32 + padding * 2;
+    let height = (bottom - top).max(0) as u32 + padding * 2;
+    let mut rect = RgbaImage::from_pixel(width, height, color);
+    round_corners(&mut rect, corner_radius);
+    let padding = padding as i64;
+    imageops::overlay(canvas, &rect, left as i64 - padding, top as i64 - padding);
+}
+
+// Multiply the glyphs drawn on the layer by a vertical gradient over `top..top + height`. The
+// text drawing blends the alpha channel too, so the glyph color is recovered from the coverage
+// first.
+fn fill_gradient(layer: &mut RgbaImage, (from, to): (Rgba<u8>, Rgba<u8>), top: i32, height: i32) {
+    for (_, y, pixel) in layer.enumerate_pixels_mut() {
+        let alpha = pixel.0[3] as f32;
+        if alpha == 0.0 {
+            continue;
+        }
+        let t = ((y as i32 - top) as f32 / (height - 1).max(1) as f32).clamp(0.0, 1.0);
+        let gradient = |i: usize| from.0[i] as f32 * (1.0 - t) + to.0[i] as f32 * t;
+        for i in 0..3 {
+            let color = (pixel.0[i] as f32 * 255.0 / alpha).min(255.0);
+            pixel.0[i] = (color * gradient(i) / 255.0).round() as u8;
+        }
+        pixel.0[3] = (alpha * gradient(3) / 255.0).round() as u8;
+    }
 }
 
 impl<'a> From<Quotes<'a>> for RgbaImage {
     fn from(quotes: Quotes<'a>) -> Self {
         // First let use calculate the quote text size
         let (bg_width, bg_height) = quotes.bg_dim;
-        let quote_area_width = bg_width - quotes.avatar_width;
-        let mut canvas = RgbaImage::new(quote_area_width, bg_height);
-        let max_text_draw_width = canvas.width() - (quotes.gap * 2);
+        let quote_area_width = bg_width.saturating_sub(quotes.avatar_width);
+        let max_text_draw_width = quote_area_width.saturating_sub(quotes.gap * 2);
+        // Text is pushed a little bit away from the avatar, there is nothing to avoid without it.
+        let shift = if quotes.avatar_width == 0 {
+            0
+        } else {
+            quotes.gap
+        };
+
+        // Thicken the strokes by roughly 1px for every 60px of font size, the passes widen the
+        // ink right to the last glyph
+        let extra_passes = if quotes.faux_bold {
+            (quotes.quote_info.raw_scale_factor() / 60.0).ceil() as i32
+        } else {
+            0
+        };
+        let quote_draw_width = max_text_draw_width as i32 - extra_passes;
 
         // Then start drawing quotes
-        let lines = Lines::new(&quotes.quote_info, max_text_draw_width as i32);
+        // Measure each char with the font it is drawn with. The line cache doesn't know the styles,
+        // so a styled quote is wrapped every time.
+        let mut lines = match quotes.line_cache {
+            Some(cache) if quotes.quote_styles.is_empty() => {
+                cache.get_or_wrap(&quotes.quote_info, quote_draw_width)
+            }
+            _ => Lines::new_styled(&quotes.quote_info, quotes.quote_styles, quote_draw_width),
+        };
+        if let Some(max_lines) = quotes.max_lines {
+            lines.truncate_with(
+                &quotes.quote_info,
+                quote_draw_width,
+                max_lines,
+                quotes.ellipsis,
+            );
+        }
         let (_, quote_height) = lines.size();
-        let mut current_draw_height = (bg_height as i32 / 2) - quote_height;
+        let subtitle = quotes
+            .subtitle_info
+            .as_ref()
+            .map(|info| (info, Lines::new(info, max_text_draw_width as i32)));
+        // Keep half of a subtitle line between the quote and the subtitle
+        let subtitle_height = subtitle
+            .as_ref()
+            .map(|(_, lines)| lines.size().1 + lines.iter().next().map_or(0, |l| l.height / 2))
+            .unwrap_or_default();
+        let block_height = quote_height + subtitle_height;
+
+        // The quote sits right above the middle line, or right below it when the username takes
+        // the upper half.
+        let mut current_draw_height = match (quotes.quote_vertical, quotes.username_position) {
+            (QuoteVertical::Center, Vertical::Bottom) => (bg_height as i32 / 2) - block_height,
+            (QuoteVertical::Center, Vertical::Top) => bg_height as i32 / 2,
+            (QuoteVertical::FromTop(y), _) => y as i32,
+            (QuoteVertical::FromBottom(y), _) => bg_height as i32 - y as i32 - block_height,
+        };
+
+        // The username block, a `\n` in it stacks the parts
+        let user_info = &quotes.user_info;
+        let username_lines = Lines::new(user_info, max_text_draw_width as i32);
+        let (_, h) = username_lines.size();
+        let handle_size = quotes
+            .handle_info
+            .as_ref()
+            .map(|info| imageproc::drawing::text_size(info.scale(), info.font(), info.text()));
+        // Keep a small gap between the username and the handle
+        let handle_gap = handle_size.map(|(_, h)| h / 4).unwrap_or_default();
+        let user_block_height = h + handle_gap + handle_size.map(|(_, h)| h).unwrap_or_default();
+        let y = match quotes.username_position {
+            // Move a tall block up so it still ends inside the image
+            Vertical::Bottom => std::cmp::min(
+                (bg_height - (bg_height / 4)) as i32,
+                bg_height as i32 - user_block_height,
+            ),
+            Vertical::Top => (bg_height / 4) as i32 - user_block_height,
+        };
+
+        if let Some(report) = quotes.report {
+            let (quote_top, quote_bottom) =
+                (current_draw_height, current_draw_height + block_height);
+            let (user_top, user_bottom) = (y, y + user_block_height);
+            report.set(QuoteReport {
+                line_count: lines.iter().len(),
+                overflowed: quote_top.min(user_top) < 0
+                    || quote_bottom.max(user_bottom) > bg_height as i32
+                    || (quote_top < user_bottom && user_top < quote_bottom),
+                missing_glyphs: quotes.missing_glyphs(),
+            });
+        }
+        if quotes.measure_only {
+            return RgbaImage::new(0, 0);
+        }
+        let mut canvas = RgbaImage::new(quote_area_width, bg_height);
+
+        // The subtitle stays next to the username, so it goes first when the username is on top
+        let draw_subtitle = |canvas: &mut RgbaImage, y: &mut i32| {
+            let Some((info, lines)) = &subtitle else {
+                return;
+            };
+            if quotes.username_position == Vertical::Bottom {
+                *y += subtitle_height - lines.size().1;
+            }
+            for line in lines {
+                let x = quote_line_x(canvas.width(), line, shift, 0);
+                imageproc::drawing::draw_text_mut(
+                    canvas,
+                    info.color(),
+                    x,
+                    *y,
+                    info.scale(),
+                    info.font(),
+                    line.text(),
+                );
+                *y += line.height;
+            }
+            if quotes.username_position == Vertical::Top {
+                *y += subtitle_height - lines.size().1;
+            }
+        };
+        if quotes.username_position == Vertical::Top {
+            draw_subtitle(&mut canvas, &mut current_draw_height);
+        }
         let quote_info = &quotes.quote_info;
+        if let Some(panel) = &quotes.text_panel {
+            draw_panel(&mut canvas, panel, &lines, shift, current_draw_height);
+        }
+        let punctuation_dy = quotes
+            .punctuation_offset
+            .map(|ratio| (ratio * quote_info.scale().y).round() as i32);
+        // With a gradient fill, the glyphs go to their own layer first and are colored later
+        let mut layer = quotes
+            .quote_gradient
+            .map(|_| RgbaImage::new(canvas.width(), canvas.height()));
+        let quote_top = current_draw_height;
         for line in lines {
-            let x =
-                centered_text_x(canvas.width(), line.width, quotes.gap) - line.first_char_width / 2;
+            let x = quote_line_x(canvas.width(), &line, shift, extra_passes);
+            let runs = style_runs(
+                &line,
+                quotes.quote_styles,
+                quote_info,
+                punctuation_dy.is_some(),
+            );
+            for (text, offset, style) in runs {
+                let mut chars = text.chars();
+                let dy = match (chars.next(), chars.next(), punctuation_dy) {
+                    (Some(c), None, Some(dy)) if is_low_punctuation(c) => dy,
+                    _ => 0,
+                };
+                for dx in 0..=extra_passes {
+                    imageproc::drawing::draw_text_mut(
+                        layer.as_mut().unwrap_or(&mut canvas),
+                        style.color,
+                        x + offset + dx,
+                        current_draw_height + dy,
+                        quote_info.scale(),
+                        style.font,
+                        text,
+                    );
+                }
+            }
+            current_draw_height += line.height;
+        }
+        if let (Some(mut layer), Some(gradient)) = (layer, quotes.quote_gradient) {
+            fill_gradient(&mut layer, gradient, quote_top, quote_height);
+            imageops::overlay(&mut canvas, &layer, 0, 0);
+        }
+        if quotes.username_position == Vertical::Bottom {
+            draw_subtitle(&mut canvas, &mut current_draw_height);
+        }
+
+        // Start drawing username
+        let align = quotes.username_align;
+
+        // Scale the icon to the cap height, and reserve its width in front of the first line
+        let icon = quotes.username_icon.and_then(|icon| {
+            let (top, bottom) = ink_vertical_bounds(user_info.scale(), user_info.font(), "H")?;
+            let height = (bottom - top).max(1) as u32;
+            let width = icon.width() as f32 * height as f32 / icon.height().max(1) as f32;
+            let icon = imageops::resize(
+                icon,
+                width.round().max(1.0) as u32,
+                height,
+                FilterType::Triangle,
+            );
+            Some((icon, top, height as i32 / 3))
+        });
+        let icon_advance = icon
+            .as_ref()
+            .map_or(0, |(icon, _, spacing)| icon.width() as i32 + spacing);
+        let line_width = |i: usize, line: &Line| match i {
+            0 => line.width + icon_advance,
+            _ => line.width,
+        };
+
+        let mut user_color = user_info.color();
+        if let Some(chip) = &quotes.username_chip {
+            let bounds = username_lines
+                .iter()
+                .enumerate()
+                .map(|(i, line)| (line_width(i, line), line))
+                .filter(|(width, _)| *width > 0)
+                .map(|(width, _)| {
+                    let x = aligned_text_x(canvas.width(), width, shift, quotes.gap, align);
+                    (x, x + width)
+                })
+                .reduce(|(l1, r1), (l2, r2)| (l1.min(l2), r1.max(r2)));
+            if let Some((left, right)) = bounds {
+                let bounds = (left, y, right, y + h);
+                fill_rounded_rect(
+                    &mut canvas,
+                    bounds,
+                    chip.padding,
+                    chip.corner_radius,
+                    chip.color,
+                );
+                user_color = chip.text_color;
+            }
+        }
+        let mut line_y = y;
+        for (i, line) in username_lines.iter().enumerate() {
+            let mut x = aligned_text_x(
+                canvas.width(),
+                line_width(i, line),
+                shift,
+                quotes.gap,
+                align,
+            );
+            if let (0, Some((icon, top, _))) = (i, &icon) {
+                imageops::overlay(&mut canvas, icon, x as i64, (line_y + top) as i64);
+                x += icon_advance;
+            }
             imageproc::drawing::draw_text_mut(
                 &mut canvas,
-                quote_info.color(),
+                user_color,
                 x,
-                current_draw_height,
-                quote_info.scale(),
-                quote_info.font(),
-                &line.text,
+                line_y,
+                user_info.scale(),
+                user_info.font(),
+                line.text(),
             );
-            current_draw_height += line.height;
+            line_y += line.height;
         }
 
-        // Start drawing username
-        let user_info = &quotes.user_info;
-        let (w, _) =
-            imageproc::drawing::text_size(user_info.scale(), user_info.font(), user_info.text());
-        let (x, y) = (
-            centered_text_x(canvas.width(), w, quotes.gap),
-            (bg_height - (bg_height / 4)) as i32,
-        );
+        if let (Some(info), Some((w, _))) = (&quotes.handle_info, handle_size) {
+            let x = aligned_text_x(canvas.width(), w, shift, quotes.gap, align);
+            imageproc::drawing::draw_text_mut(
+                &mut canvas,
+                info.color(),
+                x,
+                y + h + handle_gap,
+                info.scale(),
+                info.font(),
+                info.text(),
+            );
+        }
 
+        canvas
+    }
+}
+
+#[test]
+fn test_centered_text_x_margins() {
+    for bg_w in 100..110 {
+        for text_w in 0..=120 {
+            let x = centered_text_x(bg_w, text_w, 0);
+            let (left, right) = (x, bg_w as i32 - x - text_w);
+            assert!(
+                (0..=1).contains(&(right - left)),
+                "bg {bg_w}, text {text_w}: margins {left} and {right}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_quote_line_x() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let info = TextDrawInfo::builder()
+        .text("Wo")
+        .rgba([255, 255, 255, 255])
+        .scale(100.0)
+        .font(&font)
+        .build();
+    let lines = Lines::new(&info, 1000);
+    let line = lines.iter().next().unwrap();
+
+    let x = quote_line_x(1000, line, 30, 0);
+    let mut canvas = RgbaImage::new(1000, 200);
+    imageproc::drawing::draw_text_mut(
+        &mut canvas,
+        info.color(),
+        x,
+        0,
+        info.scale(),
+        &font,
+        line.text(),
+    );
+    let ink_start = canvas
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p.0[3] > 0)
+        .map(|(x, _, _)| x as i32)
+        .min()
+        .unwrap();
+    assert!((ink_start - centered_text_x(1000, line.ink_width(), 30)).abs() <= 1);
+}
+
+#[test]
+fn test_negative_bearing_within_bounds() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    // The hook of 'ƒ' reaches left over its origin
+    assert!(
+        font.glyph('ƒ')
+            .scaled(rusttype::Scale::uniform(400.0))
+            .h_metrics()
+            .left_side_bearing
+            < -10.0
+    );
+    let info = TextDrawInfo::builder()
+        .text("ƒƒƒƒƒƒƒƒƒƒƒƒƒƒƒƒ")
+        .rgba([255, 255, 255, 255])
+        .scale(400.0)
+        .font(&font)
+        .build();
+    let limit = 1000;
+    let lines = Lines::new(&info, limit);
+    assert!(lines.iter().count() > 1);
+
+    // Draw with a 100px margin on both sides, the ink should stay within the limit
+    for line in &lines {
+        let mut canvas = RgbaImage::new(limit as u32 + 200, 500);
+        let x = quote_line_x(limit as u32, line, 0, 0) + 100;
         imageproc::drawing::draw_text_mut(
             &mut canvas,
-            user_info.color(),
+            info.color(),
             x,
-            y,
-            user_info.scale(),
-            user_info.font(),
-            user_info.text(),
+            0,
+            info.scale(),
+            &font,
+            line.text(),
+        );
+        let ink_columns: Vec<u32> = canvas
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p.0[3] > 0)
+            .map(|(x, _, _)| x)
+            .collect();
+        let left = *ink_columns.iter().min().unwrap();
+        let right = *ink_columns.iter().max().unwrap();
+        assert!(
+            left >= 100 && right < limit as u32 + 100,
+            "{}: {left}..{right}",
+            line.text()
+        );
+    }
+}
+
+#[test]
+fn test_multiline_username() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let info = |text: &'static str, scale: f32| {
+        TextDrawInfo::builder()
+            .text(text)
+            .rgba([255, 255, 255, 255])
+            .scale(scale)
+            .font(&font)
+            .build()
+    };
+    let canvas: RgbaImage = Quotes::builder()
+        .avatar_width(0)
+        .bg_dim((800, 400))
+        .quote_info(info("", 60.0))
+        .user_info(info("Name\nRole", 40.0))
+        .build();
+
+    // Group the rows with any ink into bands
+    let mut bands: Vec<(u32, u32)> = Vec::new();
+    for y in 0..canvas.height() {
+        if !(0..canvas.width()).any(|x| canvas.get_pixel(x, y).0[3] > 0) {
+            continue;
+        }
+        match bands.last_mut() {
+            Some((_, bottom)) if *bottom + 1 == y => *bottom = y,
+            _ => bands.push((y, y)),
+        }
+    }
+    assert_eq!(bands.len(), 2, "{bands:?}");
+    assert!(bands[0].0 >= 300 && bands[1].1 < 400, "{bands:?}");
+}
+
+#[test]
+fn test_text_panel() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let canvas = |text_panel| -> RgbaImage {
+        let info = |text: &'static str, scale: f32| {
+            TextDrawInfo::builder()
+                .text(text)
+                .rgba([255, 255, 255, 255])
+                .scale(scale)
+                .font(&font)
+                .build()
+        };
+        Quotes::builder()
+            .avatar_width(0)
+            .bg_dim((800, 400))
+            .quote_info(info("Hello\nWorld", 60.0))
+            .user_info(info("", 40.0))
+            .text_panel(text_panel)
+            .build()
+    };
+    let ink_bounds = |canvas: &RgbaImage, is_ink: &dyn Fn(&Rgba<u8>) -> bool| {
+        let ink: Vec<(u32, u32)> = canvas
+            .enumerate_pixels()
+            .filter(|(_, _, p)| is_ink(p))
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        let xs = ink.iter().map(|(x, _)| *x);
+        let ys = ink.iter().map(|(_, y)| *y);
+        (
+            xs.clone().min().unwrap(),
+            ys.clone().min().unwrap(),
+            xs.max().unwrap(),
+            ys.max().unwrap(),
+        )
+    };
+
+    let (left, top, right, bottom) = ink_bounds(&canvas(None), &|p| p.0[3] > 0);
+    let panel = PanelConfig {
+        color: Rgba([0, 0, 0, 128]),
+        corner_radius: 8,
+        padding: 20,
+    };
+    let canvas = canvas(Some(panel));
+    // The translucent panel covers the text with the padding around it
+    let (panel_left, panel_top, panel_right, panel_bottom) =
+        ink_bounds(&canvas, &|p| p.0 == [0, 0, 0, 128]);
+    assert!(panel_left + 19 <= left && panel_left + 21 >= left);
+    assert!(panel_right >= right + 19 && panel_right <= right + 21);
+    assert!(panel_top + 20 <= top && panel_bottom >= bottom + 20);
+    assert_eq!(canvas.get_pixel(0, 0).0[3], 0);
+}
+
+#[test]
+fn test_punctuation_offset() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let ink_rows = |punctuation_offset| {
+        let info = |text: &'static str| {
+            TextDrawInfo::builder()
+                .text(text)
+                .rgba([255, 255, 255, 255])
+                .scale(100.0)
+                .font(&font)
+                .build()
+        };
+        let canvas: RgbaImage = Quotes::builder()
+            .avatar_width(0)
+            .bg_dim((800, 400))
+            .quote_info(info("好\u{3002}"))
+            .user_info(info(""))
+            .punctuation_offset(punctuation_offset)
+            .build();
+        // Only look at the right half, where the full stop is
+        let rows: Vec<u32> = (0..canvas.height())
+            .filter(|&y| (400..canvas.width()).any(|x| canvas.get_pixel(x, y).0[3] > 0))
+            .collect();
+        (rows[0], *rows.last().unwrap())
+    };
+
+    let (top, bottom) = ink_rows(None);
+    let (moved_top, moved_bottom) = ink_rows(Some(0.1));
+    assert_eq!((moved_top, moved_bottom), (top + 10, bottom + 10));
+}
+
+#[test]
+fn test_username_chip() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let info = |text: &'static str, scale: f32| {
+        TextDrawInfo::builder()
+            .text(text)
+            .rgba([147, 147, 147, 255])
+            .scale(scale)
+            .font(&font)
+            .build()
+    };
+    let chip = ChipConfig {
+        color: Rgba([200, 0, 0, 255]),
+        text_color: Rgba([255, 255, 255, 255]),
+        padding: 10,
+        corner_radius: 12,
+    };
+    let canvas: RgbaImage = Quotes::builder()
+        .avatar_width(0)
+        .bg_dim((800, 400))
+        .quote_info(info("", 60.0))
+        .user_info(info("Name", 40.0))
+        .username_chip(Some(chip))
+        .build();
+
+    let chip_pixels: Vec<(u32, u32)> = canvas
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p.0 == [200, 0, 0, 255])
+        .map(|(x, y, _)| (x, y))
+        .collect();
+    let left = chip_pixels.iter().map(|(x, _)| *x).min().unwrap();
+    let right = chip_pixels.iter().map(|(x, _)| *x).max().unwrap();
+    let top = chip_pixels.iter().map(|(_, y)| *y).min().unwrap();
+    let bottom = chip_pixels.iter().map(|(_, y)| *y).max().unwrap();
+    // The username is below the middle line, drawn in the chip color on the chip
+    assert!(
+        top > 200 && right - left > 60,
+        "{left}..{right}, {top}..{bottom}"
+    );
+    let white = canvas
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p.0 == [255, 255, 255, 255])
+        .map(|(x, y, _)| (x, y))
+        .collect::<Vec<_>>();
+    assert!(!white.is_empty());
+    assert!(white
+        .iter()
+        .all(|&(x, y)| (left..=right).contains(&x) && (top..=bottom).contains(&y)));
+    // Rounded corners
+    assert_eq!(canvas.get_pixel(left, top).0[3], 0);
+    assert_eq!(canvas.get_pixel(right, bottom).0[3], 0);
+}
+
+#[test]
+fn test_username_icon() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let info = |text: &'static str, scale: f32| {
+        TextDrawInfo::builder()
+            .text(text)
+            .rgba([147, 147, 147, 255])
+            .scale(scale)
+            .font(&font)
+            .build()
+    };
+    let icon = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 255]));
+    let render = |icon| -> RgbaImage {
+        Quotes::builder()
+            .avatar_width(0)
+            .bg_dim((800, 400))
+            .quote_info(info("", 60.0))
+            .user_info(info("Name", 40.0))
+            .username_icon(icon)
+            .build()
+    };
+    let bounds = |canvas: &RgbaImage, color: [u8; 4]| {
+        let pixels: Vec<(u32, u32)> = canvas
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p.0 == color)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        let left = pixels.iter().map(|(x, _)| *x).min().unwrap();
+        let right = pixels.iter().map(|(x, _)| *x).max().unwrap();
+        let top = pixels.iter().map(|(_, y)| *y).min().unwrap();
+        let bottom = pixels.iter().map(|(_, y)| *y).max().unwrap();
+        (left, top, right, bottom)
+    };
+    let grey = [147, 147, 147, 255];
+    let plain = bounds(&render(None), grey);
+    let canvas = render(Some(&icon));
+    let text = bounds(&canvas, grey);
+    let (left, top, right, bottom) = bounds(&canvas, [255, 0, 0, 255]);
+
+    // As tall as the capital N, and right before it with a small space
+    assert!(
+        top.abs_diff(text.1) <= 1 && bottom.abs_diff(text.3) <= 1,
+        "icon {top}..{bottom}, text {}..{}",
+        text.1,
+        text.3
+    );
+    assert_eq!(right - left, bottom - top);
+    assert!(right < text.0 && text.0 - right < 15, "{right} {}", text.0);
+    // The username moves right by about half of the icon, to keep the pair centered
+    let (width, height) = (right - left + 1, bottom - top + 1);
+    let half = (width + height / 3) / 2;
+    let moved = text.0 - plain.0;
+    assert!(moved.abs_diff(half) <= 1, "moved {moved}, half {half}");
+}
+
+#[test]
+fn test_quote_gradient() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let info = |text: &'static str, scale: f32| {
+        TextDrawInfo::builder()
+            .text(text)
+            .rgba([255, 255, 255, 255])
+            .scale(scale)
+            .font(&font)
+            .build()
+    };
+    let canvas: RgbaImage = Quotes::builder()
+        .avatar_width(0)
+        .bg_dim((800, 400))
+        .quote_info(info("HIH", 120.0))
+        .user_info(info("", 40.0))
+        .quote_gradient(Some((Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]))))
+        .build();
+
+    // Rows of the solid glyph pixels, from the top of the quote to the bottom
+    let solid: Vec<(u32, [u8; 4])> = canvas
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p.0[3] == 255)
+        .map(|(_, y, p)| (y, p.0))
+        .collect();
+    let (_, top) = *solid.first().unwrap();
+    let (_, bottom) = *solid.last().unwrap();
+    assert!(top[0] > top[2], "{top:?}");
+    assert!(bottom[2] > bottom[0], "{bottom:?}");
+    // No white is left from the glyph color
+    assert!(solid.iter().all(|(_, p)| p[1] == 0));
+}
+
+#[test]
+fn test_right_overhang_within_gap() {
+    // The hook of the italic 'f' reaches right over its advance, and faux bold widens it further
+    let font = std::fs::read("/usr/share/fonts/truetype/dejavu/DejaVuSerif-Italic.ttf").unwrap();
+    let font = rusttype::Font::try_from_bytes(&font).unwrap();
+    let glyph = font.glyph('f').scaled(rusttype::Scale::uniform(120.0));
+    let bounds = glyph.exact_bounding_box().unwrap();
+    assert!(bounds.max.x > glyph.h_metrics().advance_width + 10.0);
+
+    let info = |text: &'static str, scale: f32| {
+        TextDrawInfo::builder()
+            .text(text)
+            .rgba([255, 255, 255, 255])
+            .scale(scale)
+            .font(&font)
+            .build()
+    };
+    for (width, faux_bold) in [(624, true), (755, true), (624, false), (755, false)] {
+        let canvas: RgbaImage = Quotes::builder()
+            .avatar_width(0)
+            .bg_dim((width, 400))
+            .gap(20)
+            .quote_info(info("Ruff stuff of a giff", 120.0))
+            .user_info(info("", 40.0))
+            .faux_bold(faux_bold)
+            .build();
+        let ink_columns: Vec<u32> = canvas
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p.0[3] > 0)
+            .map(|(x, _, _)| x)
+            .collect();
+        let left = *ink_columns.iter().min().unwrap();
+        let right = *ink_columns.iter().max().unwrap();
+        assert!(
+            left >= 20 && right < width - 20,
+            "{width} {faux_bold}: {left}..{right}"
         );
+    }
+}
 
-        canvas
+#[test]
+fn test_wrap_with_styled_fonts() {
+    let bold = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Bold.ttc").unwrap();
+    let bold = rusttype::Font::try_from_bytes(&bold).unwrap();
+    let light = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Light.ttc").unwrap();
+    let light = rusttype::Font::try_from_bytes(&light).unwrap();
+    let text = "Plain words drawn with the light font wrap later";
+    let info = |font| {
+        TextDrawInfo::builder()
+            .text(text)
+            .rgba([255, 255, 255, 255])
+            .scale(60.0)
+            .font(font)
+            .build()
+    };
+    let style = CharStyle {
+        color: Rgba([255, 255, 255, 255]),
+        font: &light,
+    };
+    let styles = vec![style; text.chars().count()];
+
+    let mut differs = false;
+    for width in (400..1400).step_by(50) {
+        let report = Cell::default();
+        let _: RgbaImage = Quotes::builder()
+            .avatar_width(0)
+            .bg_dim((width, 600))
+            .gap(20)
+            .quote_info(info(&bold))
+            .user_info(info(&light))
+            .quote_styles(&styles)
+            .report(Some(&report))
+            .measure_only(true)
+            .build();
+        let light_lines = Lines::new(&info(&light), width as i32 - 40).iter().count();
+        let bold_lines = Lines::new(&info(&bold), width as i32 - 40).iter().count();
+        assert_eq!(report.take().line_count, light_lines, "{width}");
+        differs |= light_lines != bold_lines;
     }
+    assert!(differs);
 }
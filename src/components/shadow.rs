@@ -0,0 +1,114 @@
+use image::{imageops, GrayImage, Luma, Rgba, RgbaImage};
+use imageproc::distance_transform::Norm;
+
+use crate::{ErrorKind, Result};
+
+/// A soft shadow cast by the opaque part of the image.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DropShadow {
+    /// Sigma of the gaussian blur applied to the shadow
+    pub blur: f32,
+    /// How far the shadow is moved from the card, in pixels
+    pub offset: (i32, i32),
+    pub color: Rgba<u8>,
+    /// Grow the shadow by this many pixels on each side before blurring
+    pub spread: u32,
+}
+
+impl DropShadow {
+    /// The space added on each side of the card, large enough to hold the whole shadow. Saturates
+    /// at `u32::MAX` for huge shadows.
+    pub fn margin(&self) -> u32 {
+        let blur = (self.blur.max(0.0) * 3.0).ceil() as u32;
+        let offset = self
+            .offset
+            .0
+            .unsigned_abs()
+            .max(self.offset.1.unsigned_abs());
+        self.spread.saturating_add(blur).saturating_add(offset)
+    }
+}
+
+/// Expand the canvas by [`DropShadow::margin`] on each side, and draw the card over its shadow.
+/// The area around the card is left transparent. Fails with [`ErrorKind::InvalidDimensions`]
+/// when the expanded canvas doesn't fit in memory.
+pub fn drop_shadow(card: &RgbaImage, shadow: &DropShadow) -> Result<RgbaImage> {
+    let margin = shadow.margin();
+    let expand = |side: u32| margin.checked_mul(2).and_then(|m| side.checked_add(m));
+    let size = expand(card.width()).zip(expand(card.height()));
+    // 4 bytes per RGBA pixel
+    let Some((width, height)) =
+        size.filter(|(w, h)| w.checked_mul(*h).and_then(|n| n.checked_mul(4)).is_some())
+    else {
+        return Err(ErrorKind::InvalidDimensions {
+            width: card.width(),
+            height: card.height(),
+            reason: "drop shadow margin is too large",
+        });
+    };
+
+    let mut mask = GrayImage::new(width, height);
+    let (x, y) = (
+        margin as i64 + shadow.offset.0 as i64,
+        margin as i64 + shadow.offset.1 as i64,
+    );
+    let alpha = GrayImage::from_fn(card.width(), card.height(), |x, y| {
+        Luma([card.get_pixel(x, y).0[3]])
+    });
+    imageops::replace(&mut mask, &alpha, x, y);
+    if shadow.spread > 0 {
+        let spread = shadow.spread.min(u8::MAX as u32) as u8;
+        mask = imageproc::morphology::dilate(&mask, Norm::LInf, spread);
+    }
+    if shadow.blur > 0.0 {
+        mask = imageops::blur(&mask, shadow.blur);
+    }
+
+    let [r, g, b, a] = shadow.color.0;
+    let mut canvas = RgbaImage::from_fn(width, height, |x, y| {
+        let coverage = mask.get_pixel(x, y).0[0] as u16;
+        Rgba([r, g, b, (a as u16 * coverage / 255) as u8])
+    });
+    imageops::overlay(&mut canvas, card, margin as i64, margin as i64);
+    Ok(canvas)
+}
+
+#[test]
+fn test_drop_shadow() {
+    let card = RgbaImage::from_pixel(100, 50, Rgba([255, 255, 255, 255]));
+    let shadow = DropShadow {
+        blur: 4.0,
+        offset: (5, 5),
+        color: Rgba([0, 0, 0, 200]),
+        spread: 2,
+    };
+    let margin = shadow.margin();
+    let output = drop_shadow(&card, &shadow).unwrap();
+    assert_eq!(output.dimensions(), (100 + margin * 2, 50 + margin * 2));
+
+    // The card is untouched
+    assert_eq!(
+        output.get_pixel(margin + 50, margin + 25).0,
+        [255, 255, 255, 255]
+    );
+    // Below the card on the right, there should be shadow
+    assert!(output.get_pixel(margin + 102, margin + 52).0[3] > 0);
+    // The corner far away from the offset direction is empty
+    assert_eq!(output.get_pixel(0, 0).0[3], 0);
+}
+
+#[test]
+fn test_drop_shadow_too_large() {
+    let card = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+    let shadow = DropShadow {
+        blur: 0.0,
+        offset: (i32::MIN, 0),
+        color: Rgba([0, 0, 0, 200]),
+        spread: u32::MAX,
+    };
+    assert_eq!(shadow.margin(), u32::MAX);
+    assert!(matches!(
+        drop_shadow(&card, &shadow),
+        Err(ErrorKind::InvalidDimensions { .. })
+    ));
+}
@@ -1,7 +1,37 @@
+use super::glyph_cache::{GlyphCache, Glyphs, LineMeasure};
 use image::Rgba;
 use rusttype::Font;
 use typed_builder::TypedBuilder;
+use unicode_segmentation::UnicodeSegmentation;
 
+/// Return the first user-perceived character of the text, or an empty string if the text is empty.
+pub fn first_grapheme(text: &str) -> &str {
+    text.graphemes(true).next().unwrap_or_default()
+}
+
+/// Return the first user-perceived character that the font has glyphs for. Fallback to the very
+/// first character if none of them can be drawn.
+pub fn first_drawable_grapheme<'t>(text: &'t str, font: &Font<'_>) -> &'t str {
+    // zero width joiner and variation selectors are not drawn by themselves
+    let invisible = |c: char| c == '\u{200D}' || ('\u{FE00}'..='\u{FE0F}').contains(&c);
+    text.graphemes(true)
+        .find(|g| {
+            g.chars()
+                .filter(|c| !invisible(*c))
+                .all(|c| font.glyph(c).id().0 != 0)
+        })
+        .unwrap_or_else(|| first_grapheme(text))
+}
+
+/// Take the first drawable character of each of the first `count` words as initials.
+pub fn initials(text: &str, font: &Font<'_>, count: usize) -> String {
+    text.split_whitespace()
+        .take(count.max(1))
+        .map(|word| first_drawable_grapheme(word, font))
+        .collect()
+}
+
+/// Describe what text to draw and how to draw it.
 #[derive(TypedBuilder)]
 pub struct TextDrawInfo<'a> {
     text: &'a str,
@@ -10,6 +40,12 @@ pub struct TextDrawInfo<'a> {
     #[builder(setter(transform = |s: f32| rusttype::Scale::uniform(s)))]
     scale: rusttype::Scale,
     font: &'a Font<'a>,
+    /// Extra space in pixels between every two chars
+    #[builder(default = 0.0)]
+    letter_spacing: f32,
+    /// Reuse the glyph metrics of `font` when measuring the text
+    #[builder(default, setter(skip))]
+    glyph_cache: Option<&'a GlyphCache>,
 }
 
 impl<'a> TextDrawInfo<'a> {
@@ -32,20 +68,206 @@ impl<'a> TextDrawInfo<'a> {
     pub fn font(&self) -> &Font<'_> {
         self.font
     }
+
+    pub fn letter_spacing(&self) -> f32 {
+        self.letter_spacing
+    }
+
+    // The cache is keyed without the font, so only the crate hands one out, for the font it
+    // belongs to
+    pub(crate) fn with_glyph_cache(self, glyph_cache: &'a GlyphCache) -> Self {
+        Self {
+            glyph_cache: Some(glyph_cache),
+            ..self
+        }
+    }
+}
+
+/// Wrap the text into lines no wider than `width_limit` pixels, like the quote is wrapped, without
+/// building a [`TextDrawInfo`].
+pub fn wrap_text(text: &str, font: &Font<'_>, scale: f32, width_limit: i32) -> Lines {
+    let info = TextDrawInfo::builder()
+        .text(text)
+        .rgba([255, 255, 255, 255])
+        .scale(scale)
+        .font(font)
+        .build();
+    Lines::new(&info, width_limit)
+}
+
+/// Calculate the top and bottom of the glyph ink relative to the `y` given to
+/// `imageproc::drawing::draw_text_mut`, which puts the baseline at the font ascent.
+pub fn ink_vertical_bounds(
+    scale: rusttype::Scale,
+    font: &Font<'_>,
+    text: &str,
+) -> Option<(i32, i32)> {
+    let ascent = font.v_metrics(scale).ascent;
+    font.layout(text, scale, rusttype::point(0.0, ascent))
+        .filter_map(|g| g.pixel_bounding_box())
+        .map(|bb| (bb.min.y, bb.max.y))
+        .reduce(|(top, bottom), (t, b)| (top.min(t), bottom.max(b)))
+}
+
+/// Font and color of a single char in the quote.
+#[derive(Clone, Copy)]
+pub struct CharStyle<'a> {
+    pub color: Rgba<u8>,
+    pub font: &'a Font<'a>,
+}
+
+impl<'a> PartialEq for CharStyle<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.color == other.color && std::ptr::eq(self.font, other.font)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Emphasis {
+    Plain,
+    /// Text wrapped in `*`
+    Bold,
+    /// Text wrapped in `_`
+    Italic,
 }
 
+/// Split the text by the minimal markdown `*bold*` and `_italic_` markers. Markers without a
+/// matching closing marker are kept as literal text.
+pub fn parse_markdown(text: &str) -> Vec<(&str, Emphasis)> {
+    let mut pieces = Vec::new();
+    let mut plain_start = 0;
+    let mut cursor = 0;
+    while let Some(offset) = text[cursor..].find(['*', '_']) {
+        let open = cursor + offset;
+        let marker = text[open..].chars().next().unwrap();
+        let content_start = open + marker.len_utf8();
+        let close = text[content_start..]
+            .find(marker)
+            .map(|i| content_start + i)
+            .filter(|close| *close > content_start);
+        let Some(close) = close else {
+            cursor = content_start;
+            continue;
+        };
+
+        if plain_start < open {
+            pieces.push((&text[plain_start..open], Emphasis::Plain));
+        }
+        let emphasis = if marker == '*' {
+            Emphasis::Bold
+        } else {
+            Emphasis::Italic
+        };
+        pieces.push((&text[content_start..close], emphasis));
+        cursor = close + marker.len_utf8();
+        plain_start = cursor;
+    }
+    if plain_start < text.len() {
+        pieces.push((&text[plain_start..], Emphasis::Plain));
+    }
+    pieces
+}
+
+/// Calculate how far the pen moves after drawing the text, which is where the text drawn next to it
+/// should start.
+pub fn advance_width(scale: rusttype::Scale, font: &Font<'_>, text: &str) -> i32 {
+    font.layout(text, scale, rusttype::point(0.0, 0.0))
+        .last()
+        .map(|g| g.position().x + g.unpositioned().h_metrics().advance_width)
+        .unwrap_or_default()
+        .round() as i32
+}
+
+/// Make the text safe to measure and draw: expand tabs to 4 spaces, turn `\r\n` and lone `\r`
+/// into `\n`, and drop the other control and zero-width chars which would be drawn as boxes. With
+/// the `nfc` feature, the text is also composed into Unicode NFC.
+pub fn normalize_text(text: &str) -> String {
+    #[cfg(feature = "nfc")]
+    let text = &unicode_normalization::UnicodeNormalization::nfc(text).collect::<String>();
+
+    let invisible = |c: char| {
+        (c.is_control() && c != '\n')
+            || matches!(c, '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{FEFF}')
+    };
+    let mut normalized = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\t' => normalized.push_str("    "),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\r' => normalized.push('\n'),
+            c if invisible(c) => {}
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+// Distance between the baselines of two lines, so every line takes the same height no matter if
+// it has descenders or not
+fn line_height(info: &TextDrawInfo<'_>) -> i32 {
+    let v_metrics = info.font.v_metrics(info.scale);
+    (v_metrics.ascent - v_metrics.descent + v_metrics.line_gap).ceil() as i32
+}
+
+/// The text wrapped into lines that fit in a width limit. Iterate over it to get each [`Line`].
+#[derive(Clone)]
 pub struct Lines {
-    // line text, line width, line height
     data: Vec<Line>,
     // Total required space width and height
     size: (i32, i32),
 }
 
+/// A single wrapped line and its geometry in pixels.
+#[derive(Clone)]
 pub struct Line {
-    pub text: String,
-    pub width: i32,
-    pub height: i32,
-    pub first_char_width: i32,
+    pub(crate) text: String,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) first_char_width: i32,
+    /// Left side bearing of the first glyph, the blank space drawn before its ink.
+    pub(crate) first_char_bearing: i32,
+    /// Index of the first char of this line in the original text
+    pub(crate) start: usize,
+}
+
+impl Line {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The width of the line as measured by `imageproc::drawing::text_size`, from the origin to
+    /// the right edge of the ink, which covers a last glyph reaching past its advance.
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    /// The distance to the next line, from the ascent, descent and line gap of the font. It is
+    /// the same for every line of the same font and scale, whatever glyphs are in it.
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// The width of the first character in this line.
+    pub fn first_char_width(&self) -> i32 {
+        self.first_char_width
+    }
+
+    /// The left side bearing of the first glyph in this line, used to align the line by its ink.
+    pub fn first_char_bearing(&self) -> i32 {
+        self.first_char_bearing
+    }
+
+    /// The width from the left edge of the first glyph ink to the right edge of the last one.
+    /// It is wider than [`Line::width`] when the first glyph reaches back over its origin.
+    pub fn ink_width(&self) -> i32 {
+        self.width - self.first_char_bearing
+    }
+
+    /// The char index in the original text where this line starts.
+    pub fn start(&self) -> usize {
+        self.start
+    }
 }
 
 impl std::iter::IntoIterator for Lines {
@@ -69,50 +291,115 @@ impl<'a> std::iter::IntoIterator for &'a Lines {
 }
 
 impl Lines {
+    /// Wrap the text in `info` into lines no wider than `limit` pixels. A `\n` in the text always
+    /// starts a new line. Lines only break between grapheme clusters, so a combining mark stays
+    /// with its base char.
     pub fn new(info: &TextDrawInfo<'_>, limit: i32) -> Self {
+        Self::new_styled(info, &[], limit)
+    }
+
+    /// Same as [`Lines::new`], but measure each char with its font in `styles`, indexed by the
+    /// char position in the text, like the styled quote is drawn. Chars without a style use the
+    /// font of `info`.
+    pub fn new_styled<'a>(info: &TextDrawInfo<'a>, styles: &[CharStyle<'a>], limit: i32) -> Self {
         let mut lines = Vec::new();
         let mut buffer = String::new();
         let (mut text_area_w, mut text_area_h) = (0, 0);
-        let total = info.text.chars().count();
-
-        // TODO: This is inefficient, guess and step with multiple characters
-        for (idx, char) in info.text.chars().enumerate() {
-            buffer.push(char);
-
-            let (line_w, line_h) = imageproc::drawing::text_size(info.scale, info.font, &buffer);
-
-            let drop_needed = line_w >= limit || char == '\n';
-            let match_newline = drop_needed || idx == total - 1;
-            if match_newline {
-                let new_line = if drop_needed {
-                    let n = buffer.chars().count();
-                    let s = buffer.chars().take(n - 1).collect::<String>();
-                    buffer.clear();
-                    // we need to put the char back to next line, except the '\n' character.
-                    if line_w >= limit {
-                        buffer.push(char);
-                    }
-
-                    s
-                } else {
-                    buffer.to_string()
+        let mut line_start = 0;
+
+        let local_cache;
+        let cache = match info.glyph_cache {
+            Some(cache) if styles.is_empty() => cache,
+            // The glyph cache only serves one font, so styled text skips it
+            _ => {
+                local_cache = match styles {
+                    [] => GlyphCache::default(),
+                    _ => GlyphCache::new(0),
                 };
+                &local_cache
+            }
+        };
+        let mut glyphs = cache.lock();
+        // Measure the line incrementally, instead of laying out the whole buffer for every char
+        let mut measure = LineMeasure::new(info.font, info.scale);
+        let mut first_char_measure = LineMeasure::new(info.font, info.scale);
+        let font_of = |idx: usize| styles.get(idx).map_or(info.font, |style| style.font);
+        let bearing_of = |idx: usize, c: char| {
+            let glyph = font_of(idx).glyph(c).scaled(info.scale);
+            glyph.h_metrics().left_side_bearing.round() as i32
+        };
+        let mut bearing = 0;
+        let line_height = line_height(info);
+        // Width of the buffer without the latest grapheme, which is the line width when it is
+        // dropped
+        let mut kept_width = 0;
+        let push_grapheme =
+            |measure: &mut LineMeasure<'a>, glyphs: &mut Glyphs, grapheme: &str, idx: usize| {
+                grapheme
+                    .chars()
+                    .enumerate()
+                    .fold(0, |_, (i, c)| measure.push(glyphs, font_of(idx + i), c).0)
+            };
+
+        let mut push_line = |glyphs: &mut Glyphs, text: String, width: i32, start: usize| {
+            let first_char = text.chars().next();
+            let (fcw, _) = first_char
+                .map(|c| {
+                    first_char_measure.clear();
+                    first_char_measure.push(glyphs, font_of(start), c)
+                })
+                .unwrap_or_default();
+            lines.push(Line {
+                text,
+                width,
+                height: line_height,
+                first_char_width: fcw,
+                first_char_bearing: first_char.map(|c| bearing_of(start, c)).unwrap_or_default(),
+                start,
+            });
+            text_area_w = std::cmp::max(text_area_w, width);
+            text_area_h += line_height;
+        };
+
+        // Index of the first char of the grapheme in the whole text
+        let mut idx = 0;
+        for grapheme in info.text.graphemes(true) {
+            let first_char = grapheme.chars().next().unwrap_or_default();
+            if buffer.is_empty() {
+                bearing = bearing_of(idx, first_char);
+            }
+            buffer.push_str(grapheme);
+
+            let line_w = push_grapheme(&mut measure, &mut glyphs, grapheme, idx);
+            let spacing = info.letter_spacing * (buffer.chars().count() - 1) as f32;
+            let line_w = line_w + spacing.round() as i32;
+            let buffer_width = std::mem::replace(&mut kept_width, line_w);
 
-                let (fcw, _) = imageproc::drawing::text_size(
-                    info.scale,
-                    info.font,
-                    &new_line.chars().next().unwrap().to_string(),
-                );
-                lines.push(Line {
-                    text: new_line,
-                    width: line_w,
-                    height: line_h,
-                    first_char_width: fcw,
-                });
-
-                text_area_w = std::cmp::max(text_area_w, line_w);
-                text_area_h += line_h;
+            // Break by the ink width, so a first glyph reaching left doesn't push the line out
+            let overflow = line_w - bearing >= limit;
+            let is_newline = grapheme.ends_with('\n');
+            let drop_needed = overflow || is_newline;
+            let next_idx = idx + grapheme.chars().count();
+            if drop_needed {
+                let new_line = buffer[..buffer.len() - grapheme.len()].to_string();
+                push_line(&mut glyphs, new_line, buffer_width, line_start);
+                buffer.clear();
+                measure.clear();
+                kept_width = 0;
+                // we need to put the grapheme back to next line, except the '\n' character.
+                if overflow && !is_newline {
+                    buffer.push_str(grapheme);
+                    kept_width = push_grapheme(&mut measure, &mut glyphs, grapheme, idx);
+                    bearing = bearing_of(idx, first_char);
+                    line_start = idx;
+                } else {
+                    line_start = next_idx;
+                }
             }
+            idx = next_idx;
+        }
+        if !buffer.is_empty() {
+            push_line(&mut glyphs, buffer, kept_width, line_start);
         }
 
         Self {
@@ -121,7 +408,259 @@ impl Lines {
         }
     }
 
+    /// The total width and height required to draw all the lines.
     pub fn size(&self) -> (i32, i32) {
         self.size
     }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Line> {
+        self.data.iter()
+    }
+
+    /// Keep at most `max_lines` lines. When some lines are dropped, the last kept line ends with
+    /// an ellipsis, and loses as many trailing chars as needed to stay within `limit` pixels.
+    pub fn truncate(&mut self, info: &TextDrawInfo<'_>, limit: i32, max_lines: usize) {
+        self.truncate_with(info, limit, max_lines, "\u{2026}");
+    }
+
+    /// Same as [`Lines::truncate`], but end the last kept line with the given ellipsis text.
+    pub fn truncate_with(
+        &mut self,
+        info: &TextDrawInfo<'_>,
+        limit: i32,
+        max_lines: usize,
+        ellipsis: &str,
+    ) {
+        if self.data.len() <= max_lines {
+            return;
+        }
+        self.data.truncate(max_lines);
+
+        if let Some(last) = self.data.last_mut() {
+            let measure = |text: &str| {
+                let (w, _) = imageproc::drawing::text_size(info.scale, info.font, text);
+                let spacing = info.letter_spacing * text.chars().count().saturating_sub(1) as f32;
+                w + spacing.round() as i32
+            };
+            let bearing_of = |c: char| {
+                let glyph = info.font.glyph(c).scaled(info.scale);
+                glyph.h_metrics().left_side_bearing.round() as i32
+            };
+            let fallback = ellipsis.chars().next().unwrap_or(' ');
+            let mut text = last.text.clone();
+            let width = loop {
+                let bearing = bearing_of(text.chars().next().unwrap_or(fallback));
+                let w = measure(&format!("{text}{ellipsis}"));
+                // Drop whole graphemes, a combining mark can't be left without its base
+                let Some((last_grapheme, _)) = text.grapheme_indices(true).next_back() else {
+                    break w;
+                };
+                if w - bearing < limit {
+                    break w;
+                }
+                text.truncate(last_grapheme);
+            };
+            text.push_str(ellipsis);
+
+            let first_char = text.chars().next().unwrap_or(fallback);
+            last.first_char_width = measure(&first_char.to_string());
+            last.first_char_bearing = bearing_of(first_char);
+            last.text = text;
+            last.width = width;
+        }
+
+        self.size = self.data.iter().fold((0, 0), |(w, h), line| {
+            (std::cmp::max(w, line.width), h + line.height)
+        });
+    }
+}
+
+#[test]
+fn test_first_grapheme() {
+    assert_eq!(first_grapheme("Émile"), "É");
+    // decomposed form: E + combining acute accent
+    assert_eq!(first_grapheme("E\u{301}mile"), "E\u{301}");
+    assert_eq!(first_grapheme(""), "");
+}
+
+#[test]
+fn test_flag_emoji_grapheme() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = Font::try_from_bytes(&font).unwrap();
+    let letter = first_drawable_grapheme("🇨🇳Alice", &font);
+    // Either the font draws the whole flag, or we skip it, but never half of the flag
+    let has_flag = "🇨🇳".chars().all(|c| font.glyph(c).id().0 != 0);
+    assert_eq!(letter, if has_flag { "🇨🇳" } else { "A" });
+}
+
+#[test]
+fn test_initials() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = Font::try_from_bytes(&font).unwrap();
+    assert_eq!(initials("John Doe", &font, 1), "J");
+    assert_eq!(initials("John Doe", &font, 2), "JD");
+    assert_eq!(initials("John", &font, 2), "J");
+}
+
+#[test]
+fn test_standalone_lines() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = Font::try_from_bytes(&font).unwrap();
+    let info = TextDrawInfo::builder()
+        .text("first line\nsecond")
+        .rgba([255, 255, 255, 255])
+        .scale(40.0)
+        .font(&font)
+        .build();
+    let lines = Lines::new(&info, 1000);
+
+    let texts: Vec<&str> = lines.iter().map(|line| line.text()).collect();
+    assert_eq!(texts, ["first line", "second"]);
+    let (width, height) = lines.size();
+    assert_eq!(width, lines.iter().map(|l| l.width()).max().unwrap());
+    assert_eq!(height, lines.iter().map(|l| l.height()).sum::<i32>());
+    for line in &lines {
+        assert!(line.first_char_width() > 0 && line.first_char_width() < line.width());
+    }
+}
+
+#[test]
+fn test_truncate_lines() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = Font::try_from_bytes(&font).unwrap();
+    let info = TextDrawInfo::builder()
+        .text("A long quote that is wrapped into many lines, but only two of them are kept.")
+        .rgba([255, 255, 255, 255])
+        .scale(40.0)
+        .font(&font)
+        .build();
+    let mut lines = Lines::new(&info, 300);
+    assert!(lines.iter().len() > 2);
+
+    lines.truncate(&info, 300, 2);
+    assert_eq!(lines.iter().len(), 2);
+    let last = lines.iter().last().unwrap();
+    assert!(last.text().ends_with('\u{2026}'));
+    assert!(last.width() < 300);
+    let (w, _) = imageproc::drawing::text_size(info.scale, &font, last.text());
+    assert_eq!(last.width(), w);
+}
+
+#[test]
+fn test_truncate_custom_ellipsis() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = Font::try_from_bytes(&font).unwrap();
+    let info = TextDrawInfo::builder()
+        .text("A long quote that is wrapped into many lines, but only two of them are kept.")
+        .rgba([255, 255, 255, 255])
+        .scale(40.0)
+        .font(&font)
+        .build();
+    let mut lines = Lines::new(&info, 300);
+    let second = lines.iter().nth(1).unwrap().text().to_string();
+
+    lines.truncate_with(&info, 300, 2, "...");
+    assert_eq!(lines.iter().len(), 2);
+    // The ellipsis replaces the end of the second line, and is measured with it
+    let last = lines.iter().last().unwrap();
+    let kept = last.text().strip_suffix("...").unwrap();
+    assert!(second.starts_with(kept) && kept.len() < second.len());
+    assert!(last.width() < 300);
+    let (w, _) = imageproc::drawing::text_size(info.scale, &font, last.text());
+    assert_eq!(last.width(), w);
+}
+
+#[test]
+fn test_parse_markdown() {
+    assert_eq!(
+        parse_markdown("hello *world*"),
+        [("hello ", Emphasis::Plain), ("world", Emphasis::Bold)]
+    );
+    assert_eq!(
+        parse_markdown("_a_ b *c"),
+        [("a", Emphasis::Italic), (" b *c", Emphasis::Plain)]
+    );
+    assert_eq!(parse_markdown("**"), [("**", Emphasis::Plain)]);
+}
+
+#[test]
+fn test_normalize_text() {
+    assert_eq!(normalize_text("a\tb\r\nc"), "a    b\nc");
+    assert_eq!(normalize_text("a\rb\u{200B}\u{0007}c\u{FEFF}"), "a\nbc");
+    assert_eq!(normalize_text("first line\nsecond"), "first line\nsecond");
+}
+
+#[cfg(all(test, feature = "nfc"))]
+#[test]
+fn test_normalize_text_nfc() {
+    assert_eq!(normalize_text("caf\u{0065}\u{0301}"), "caf\u{00E9}");
+}
+
+#[test]
+fn test_line_height_from_v_metrics() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = Font::try_from_bytes(&font).unwrap();
+    let info = TextDrawInfo::builder()
+        .text("HELLO\ngjpqy\n\nend")
+        .rgba([255, 255, 255, 255])
+        .scale(60.0)
+        .font(&font)
+        .build();
+    let lines = Lines::new(&info, 1000);
+    let heights: Vec<i32> = lines.iter().map(|l| l.height()).collect();
+    // The glyph boxes differ, but all lines, even the empty one, take the same height
+    let caps = imageproc::drawing::text_size(info.scale, &font, "HELLO").1;
+    let descenders = imageproc::drawing::text_size(info.scale, &font, "gjpqy").1;
+    assert!(caps < descenders);
+    assert_eq!(heights, [heights[0]; 4]);
+    assert!(heights[0] >= descenders);
+}
+
+#[test]
+fn test_wrap_text() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = Font::try_from_bytes(&font).unwrap();
+    let width = advance_width(rusttype::Scale::uniform(40.0), &font, "hello world");
+    let lines = wrap_text(
+        "hello world hello world hello world",
+        &font,
+        40.0,
+        width + 1,
+    );
+    assert_eq!(lines.iter().count(), 3);
+    assert_eq!(
+        wrap_text("hello\nworld", &font, 40.0, 1000).iter().count(),
+        2
+    );
+}
+
+#[test]
+fn test_wrap_keeps_combining_marks() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let font = Font::try_from_bytes(&font).unwrap();
+    let text = "e\u{301}".repeat(40);
+    let info = TextDrawInfo::builder()
+        .text(&text)
+        .rgba([255, 255, 255, 255])
+        .scale(40.0)
+        .font(&font)
+        .build();
+    for limit in 50..120 {
+        let mut lines = Lines::new(&info, limit);
+        assert!(lines.iter().count() > 1);
+        for line in &lines {
+            assert!(line.text().starts_with('e'), "{limit}: {}", line.text());
+            assert!(line.text().ends_with('\u{301}'), "{limit}: {}", line.text());
+            assert_eq!(line.start() % 2, 0);
+        }
+        let joined: String = lines.iter().map(|line| line.text()).collect();
+        assert_eq!(joined, text);
+
+        lines.truncate(&info, limit, 1);
+        let last = lines.iter().next().unwrap().text();
+        assert!(
+            last == "\u{2026}" || last.ends_with("\u{301}\u{2026}"),
+            "{limit}: {last}"
+        );
+    }
 }
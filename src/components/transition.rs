@@ -1,24 +1,156 @@
 use image::{imageops, Rgba, RgbaImage};
 use typed_builder::TypedBuilder;
 
-/// A component for constructing a gradient overlay.
+/// Which way the gradient goes from the starting color to the ending color.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GradientDirection {
+    #[default]
+    LeftToRight,
+    RightToLeft,
+    TopToBottom,
+    BottomToTop,
+}
+
+/// A component for constructing a linear gradient of the given size. The producer uses it to fade
+/// the avatar into the background color.
 #[derive(TypedBuilder)]
 #[builder(build_method(into = RgbaImage))]
 pub struct Transition {
-    avatar_width: u32,
-    bg_height: u32,
-
+    width: u32,
+    height: u32,
     #[builder(default = Rgba::from([0, 0, 0, 0]))]
     starting_color: Rgba<u8>,
     #[builder(default = Rgba::from([0, 0, 0, 255]))]
     ending_color: Rgba<u8>,
+    #[builder(default)]
+    direction: GradientDirection,
 }
 
 impl From<Transition> for RgbaImage {
     // Call the Builder().build() will convert the Transition type into ImgBuffer
     fn from(trans: Transition) -> Self {
-        let mut overlay = RgbaImage::new(&trans.avatar_width / 3, trans.bg_height);
-        imageops::horizontal_gradient(&mut overlay, &trans.starting_color, &trans.ending_color);
+        let mut overlay = RgbaImage::new(trans.width, trans.height);
+        let (start, end) = (&trans.starting_color, &trans.ending_color);
+        let length = match trans.direction {
+            GradientDirection::LeftToRight | GradientDirection::RightToLeft => trans.width,
+            GradientDirection::TopToBottom | GradientDirection::BottomToTop => trans.height,
+        };
+        // The `image` gradients divide by the length minus one, a single pixel has no room to fade
+        if length < 2 {
+            return RgbaImage::from_pixel(trans.width, trans.height, *end);
+        }
+        match trans.direction {
+            GradientDirection::LeftToRight => {
+                imageops::horizontal_gradient(&mut overlay, start, end)
+            }
+            GradientDirection::RightToLeft => {
+                imageops::horizontal_gradient(&mut overlay, end, start)
+            }
+            GradientDirection::TopToBottom => imageops::vertical_gradient(&mut overlay, start, end),
+            GradientDirection::BottomToTop => imageops::vertical_gradient(&mut overlay, end, start),
+        }
         overlay
     }
 }
+
+/// 4x4 Bayer matrix for ordered dithering
+const BAYER: [[i16; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Perturb the pixels in the given columns by -2 to +2 in a fixed pattern, so a smooth gradient
+/// doesn't turn into visible bands after JPEG compression.
+pub fn dither(img: &mut RgbaImage, x: u32, width: u32) {
+    let end = std::cmp::min(x + width, img.width());
+    for px in x..end {
+        for py in 0..img.height() {
+            let delta = BAYER[(py % 4) as usize][(px % 4) as usize] * 5 / 16 - 2;
+            let pixel = img.get_pixel_mut(px, py);
+            for channel in &mut pixel.0[..3] {
+                *channel = (*channel as i16 + delta).clamp(0, 255) as u8;
+            }
+        }
+    }
+}
+
+/// Fade the alpha of the rightmost `width` columns linearly towards zero, so the image edge blends
+/// into whatever it is composited on instead of leaving a hard seam.
+pub fn feather(img: &mut RgbaImage, width: u32) {
+    let width = width.min(img.width());
+    for offset in 0..width {
+        // The rightmost column keeps 1/(width+1) of its alpha
+        let keep = (offset + 1) as f32 / (width + 1) as f32;
+        let px = img.width() - 1 - offset;
+        for py in 0..img.height() {
+            let alpha = &mut img.get_pixel_mut(px, py).0[3];
+            *alpha = (*alpha as f32 * keep).round() as u8;
+        }
+    }
+}
+
+#[test]
+fn test_feather() {
+    let mut img = RgbaImage::from_pixel(100, 10, Rgba([255, 255, 255, 255]));
+    feather(&mut img, 4);
+    let alpha = |x: u32| img.get_pixel(x, 5).0[3];
+    assert_eq!(alpha(95), 255);
+    assert_eq!(alpha(99), 51);
+    assert!((96..99).all(|x| alpha(x) > alpha(x + 1)));
+
+    let mut img = RgbaImage::from_pixel(100, 10, Rgba([255, 255, 255, 255]));
+    feather(&mut img, 0);
+    assert_eq!(img.get_pixel(99, 5).0[3], 255);
+}
+
+#[test]
+fn test_gradient_max_alpha() {
+    let overlay: RgbaImage = Transition::builder()
+        .width(100)
+        .height(10)
+        .ending_color(Rgba([0, 0, 0, 180]))
+        .build();
+    let last_column = overlay.width() - 1;
+    assert_eq!(overlay.get_pixel(last_column, 5).0[3], 180);
+    assert_eq!(overlay.get_pixel(0, 5).0[3], 0);
+}
+
+#[test]
+fn test_standalone_gradient() {
+    let (red, blue) = (Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]));
+    let gradient = |direction| -> RgbaImage {
+        Transition::builder()
+            .width(200)
+            .height(50)
+            .starting_color(red)
+            .ending_color(blue)
+            .direction(direction)
+            .build()
+    };
+
+    let overlay = gradient(GradientDirection::LeftToRight);
+    assert_eq!(overlay.dimensions(), (200, 50));
+    assert_eq!(*overlay.get_pixel(0, 25), red);
+    assert_eq!(*overlay.get_pixel(199, 25), blue);
+
+    let overlay = gradient(GradientDirection::RightToLeft);
+    assert_eq!(*overlay.get_pixel(0, 25), blue);
+    assert_eq!(*overlay.get_pixel(199, 25), red);
+
+    let overlay = gradient(GradientDirection::TopToBottom);
+    assert_eq!(*overlay.get_pixel(100, 0), red);
+    assert_eq!(*overlay.get_pixel(100, 49), blue);
+
+    let overlay = gradient(GradientDirection::BottomToTop);
+    assert_eq!(*overlay.get_pixel(100, 0), blue);
+    assert_eq!(*overlay.get_pixel(100, 49), red);
+}
+
+#[test]
+fn test_single_pixel_gradient() {
+    let gradient: RgbaImage = Transition::builder().width(1).height(4).build();
+    assert_eq!(gradient.get_pixel(0, 3).0, [0, 0, 0, 255]);
+    let gradient: RgbaImage = Transition::builder()
+        .width(0)
+        .height(4)
+        .direction(GradientDirection::TopToBottom)
+        .build();
+    assert_eq!(gradient.dimensions(), (0, 4));
+}
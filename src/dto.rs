@@ -0,0 +1,109 @@
+//! Plain data version of [`ImgConfig`], for configs received as JSON or other serde formats.
+
+use crate::{ImgConfig, SpooledData};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Where the avatar of an [`ImgConfigDto`] comes from.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AvatarDto {
+    /// Path to the image file on the local disk. URLs are not fetched, download them first.
+    Path(String),
+    /// Image file data in standard base64.
+    Base64(#[serde(with = "base64_data")] Vec<u8>),
+    /// Letter avatar, see [`SpooledData::Initials`].
+    Initials {
+        text: String,
+        #[serde(default)]
+        color: Option<[u8; 4]>,
+    },
+}
+
+/// Owned, serializable quote config. Borrow an [`ImgConfig`] from it with
+/// [`ImgConfigDto::config`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ImgConfigDto {
+    pub quote: String,
+    pub username: String,
+    #[serde(default)]
+    pub parse_markdown: bool,
+    #[serde(default)]
+    pub handle: Option<String>,
+    #[serde(default)]
+    pub subtitle: Option<String>,
+    #[serde(default)]
+    pub avatar: Option<AvatarDto>,
+}
+
+impl ImgConfigDto {
+    /// Create the config to render, which borrows the avatar data from this DTO.
+    pub fn config(&self) -> ImgConfig<'_> {
+        let avatar = self.avatar.as_ref().map(|avatar| match avatar {
+            AvatarDto::Path(path) => SpooledData::OnDisk(Path::new(path)),
+            AvatarDto::Base64(data) => SpooledData::InMem(data),
+            AvatarDto::Initials { text, color } => SpooledData::Initials {
+                text: text.clone(),
+                color: *color,
+            },
+        });
+        let mut config = ImgConfig::builder()
+            .quote(&self.quote)
+            .username(&self.username)
+            .parse_markdown(self.parse_markdown)
+            .build();
+        config.handle = self.handle.clone();
+        config.subtitle = self.subtitle.clone();
+        config.avatar = avatar;
+        config
+    }
+}
+
+mod base64_data {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD.decode(text).map_err(serde::de::Error::custom)
+    }
+}
+
+#[test]
+fn test_deserialize_config() {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let avatar = STANDARD.encode(std::fs::read("./assets/avatar.png").unwrap());
+    let json = format!(
+        r#"{{
+            "quote": "Hello",
+            "username": "@ksyxmeow",
+            "subtitle": "in the group chat",
+            "avatar": {{ "base64": "{avatar}" }}
+        }}"#
+    );
+    let dto: ImgConfigDto = serde_json::from_str(&json).unwrap();
+    assert_eq!(dto.subtitle.as_deref(), Some("in the group chat"));
+    assert!(matches!(dto.avatar, Some(AvatarDto::Base64(_))));
+
+    // The round trip keeps everything
+    let again: ImgConfigDto = serde_json::from_str(&serde_json::to_string(&dto).unwrap()).unwrap();
+    assert_eq!(again, dto);
+
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
+    let producer = crate::QuoteProducer::builder()
+        .font(crate::FontSet::single(&font, 0).unwrap())
+        .output_size(800, 400)
+        .build();
+    let image = producer.make_raw_image(&dto.config()).unwrap();
+    assert_eq!(image.dimensions(), (800, 400));
+
+    let missing = serde_json::from_str::<ImgConfigDto>(r#"{ "quote": "Hello" }"#);
+    assert!(missing.is_err());
+    let missing = serde_json::from_str::<ImgConfigDto>(r#"{ "username": "@ksyxmeow" }"#);
+    assert!(missing.is_err());
+}
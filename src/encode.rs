@@ -0,0 +1,143 @@
+//! Encoders for the output options that `RgbaImage::write_to` doesn't expose.
+
+use crate::ChromaSubsampling;
+use image::error::{EncodingError, ImageFormatHint, LimitError, LimitErrorKind};
+use image::{ImageError, ImageFormat, RgbaImage};
+use jpeg_encoder::SamplingFactor;
+
+/// Encode the image as PNG, with the quote and username in `iTXt` chunks which hold UTF-8 text,
+/// and the resolution in the `pHYs` chunk.
+pub(crate) fn write_png(
+    image: &RgbaImage,
+    text: Option<(&str, &str)>,
+    dpi: Option<u32>,
+) -> Result<Vec<u8>, ImageError> {
+    let encoding_err = |err| {
+        ImageError::Encoding(EncodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Png),
+            err,
+        ))
+    };
+
+    let mut buffer = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buffer, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // Pin the compression, so the same pixels are always written as the same bytes
+    encoder.set_compression(png::Compression::Default);
+    encoder.set_filter(png::FilterType::Sub);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::NonAdaptive);
+    if let Some((quote, username)) = text {
+        encoder
+            .add_itxt_chunk("Description".to_string(), quote.to_string())
+            .map_err(encoding_err)?;
+        encoder
+            .add_itxt_chunk("Author".to_string(), username.to_string())
+            .map_err(encoding_err)?;
+    }
+    if let Some(dpi) = dpi {
+        // PNG only knows pixels per meter
+        let ppm = (dpi as f64 / 0.0254).round() as u32;
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: ppm,
+            yppu: ppm,
+            unit: png::Unit::Meter,
+        }));
+    }
+    let mut writer = encoder.write_header().map_err(encoding_err)?;
+    writer
+        .write_image_data(image.as_raw())
+        .map_err(encoding_err)?;
+    writer.finish().map_err(encoding_err)?;
+    Ok(buffer)
+}
+
+/// Whether the encoded JPEG starts with a JFIF APP0 segment right after the SOI marker.
+fn has_jfif_segment(jpeg: &[u8]) -> bool {
+    jpeg.get(2..4) == Some(&[0xFF, 0xE0]) && jpeg.get(6..11) == Some(b"JFIF\0")
+}
+
+/// Insert a COM segment after the SOI marker of the encoded JPEG, or after the JFIF segment if
+/// there is one, since JFIF requires its APP0 segment to come first.
+pub(crate) fn insert_jpeg_comment(jpeg: Vec<u8>, comment: &str) -> Vec<u8> {
+    // The segment length takes 2 bytes and counts itself, cut long comments at a char boundary
+    let mut end = comment.len().min(u16::MAX as usize - 2);
+    while !comment.is_char_boundary(end) {
+        end -= 1;
+    }
+    let comment = &comment.as_bytes()[..end];
+    let length = (comment.len() + 2) as u16;
+
+    let at = if has_jfif_segment(&jpeg) {
+        // The segment length after the marker counts itself but not the marker
+        2 + 2 + u16::from_be_bytes([jpeg[4], jpeg[5]]) as usize
+    } else {
+        2
+    };
+    let mut output = Vec::with_capacity(jpeg.len() + comment.len() + 4);
+    output.extend_from_slice(&jpeg[..at]);
+    output.extend_from_slice(&[0xFF, 0xFE]);
+    output.extend_from_slice(&length.to_be_bytes());
+    output.extend_from_slice(comment);
+    output.extend_from_slice(&jpeg[at..]);
+    output
+}
+
+/// Set the density in the JFIF segment of the encoded JPEG to `dpi` dots per inch, or insert the
+/// segment right after the SOI marker if there is none.
+pub(crate) fn set_jpeg_density(mut jpeg: Vec<u8>, dpi: u32) -> Vec<u8> {
+    let dpi = dpi.min(u16::MAX as u32) as u16;
+    // Units are 1 for dots per inch, then the horizontal and vertical density
+    let mut density = vec![1];
+    density.extend_from_slice(&dpi.to_be_bytes());
+    density.extend_from_slice(&dpi.to_be_bytes());
+
+    if has_jfif_segment(&jpeg) {
+        jpeg[13..18].copy_from_slice(&density);
+        return jpeg;
+    }
+    let mut output = Vec::with_capacity(jpeg.len() + 18);
+    output.extend_from_slice(&jpeg[..2]);
+    output.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x10]);
+    // Identifier and version 1.1, then no thumbnail after the density
+    output.extend_from_slice(b"JFIF\0\x01\x01");
+    output.extend_from_slice(&density);
+    output.extend_from_slice(&[0, 0]);
+    output.extend_from_slice(&jpeg[2..]);
+    output
+}
+
+/// Encode the image as JPEG with the options that the `image` encoder lacks. A progressive JPEG
+/// shows a blurry preview while it is loading.
+pub(crate) fn write_jpeg(
+    image: &RgbaImage,
+    quality: u8,
+    progressive: bool,
+    chroma_subsampling: Option<ChromaSubsampling>,
+) -> Result<Vec<u8>, ImageError> {
+    let encoding_err = |err| {
+        ImageError::Encoding(EncodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Jpeg),
+            err,
+        ))
+    };
+
+    let mut buffer = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut buffer, quality);
+    encoder.set_progressive(progressive);
+    if let Some(sampling) = chroma_subsampling {
+        encoder.set_sampling_factor(match sampling {
+            ChromaSubsampling::Yuv444 => SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => SamplingFactor::R_4_2_0,
+        });
+    }
+    // JPEG can't be larger than 65535px on each side
+    let too_large = |_| ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError));
+    let width = u16::try_from(image.width()).map_err(too_large)?;
+    let height = u16::try_from(image.height()).map_err(too_large)?;
+    encoder
+        .encode(image.as_raw(), width, height, jpeg_encoder::ColorType::Rgba)
+        .map_err(encoding_err)?;
+    Ok(buffer)
+}
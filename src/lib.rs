@@ -8,7 +8,7 @@
 //! # Usage
 //!
 //! ```rust
-//! use make_quote::{QuoteProducer, ImgConfig};
+//! use make_quote::{FontSet, QuoteProducer, ImgConfig};
 //!
 //! // First of all, load an font into memory
 //! let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc").unwrap();
@@ -17,7 +17,7 @@
 //! let bold_font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Bold.ttc").unwrap();
 //! let light_font = include_bytes!("/usr/share/fonts/noto-cjk/NotoSansCJK-Light.ttc");
 //! let producer = QuoteProducer::builder()
-//!     .font(&bold_font, light_font)
+//!     .font(FontSet::new(&bold_font, light_font).unwrap())
 //!     .output_size(1920, 1080) // optional
 //!     .font_scale(120.0)       // optional
 //!     .build();
@@ -39,46 +39,467 @@
 //! This will generate the below output:
 //!
 //! <img src="https://github.com/Avimitin/make-quote/raw/master/assets/test.jpg"/>
+//!
+//! # Custom layouts
+//!
+//! [`Background`], [`Avatar`], [`Transition`] and [`Quotes`] are the building blocks of
+//! [`QuoteProducer::make_image`]. Each of them is a builder which turns into an [`RgbaImage`] on
+//! `build()`, so you can composite them in your own order with `image::imageops::overlay`. They
+//! are lower level than the producer, and their builder fields may change in minor releases as
+//! the default layout evolves.
 
+use std::cell::Cell;
 use std::fmt::Display;
-use std::io::Cursor;
+use std::io::{Cursor, Read};
 use std::path::Path;
 
+use image::codecs::jpeg::JpegEncoder;
 use image::imageops;
-use image::{ImageError, ImageFormat};
+pub use image::imageops::FilterType;
+use image::{DynamicImage, ImageError, ImageFormat, Rgba, RgbaImage};
 
-use rusttype::Font;
+pub use rusttype::Font;
 use typed_builder::TypedBuilder;
 
 mod components;
+#[cfg(feature = "serde")]
+mod dto;
+mod encode;
+#[cfg(test)]
+mod testing;
+
+pub use components::{
+    wrap_text, Avatar, AvatarFilter, AvatarShape, Background, BorderConfig, ChipConfig, CropAnchor,
+    DropShadow, GradientDirection, Horizontal, Line, Lines, OutputFilter, PanelConfig, QuoteReport,
+    QuoteVertical, Quotes, TextDrawInfo, Transition, Vertical,
+};
+#[cfg(feature = "serde")]
+pub use dto::{AvatarDto, ImgConfigDto};
+
+/// Receive the intermediate images of [`QuoteProducer::make_image`], see the `debug-steps` feature.
+#[cfg(feature = "debug-steps")]
+pub type StepHook = Box<dyn Fn(&str, &RgbaImage) + Send + Sync>;
 
+/// Turn [`ImgConfig`]s into quote images. The font has no default, so a producer without it
+/// doesn't build:
+///
+/// ```compile_fail
+/// let producer = make_quote::QuoteProducer::builder().build();
+/// ```
 #[derive(TypedBuilder)]
 pub struct QuoteProducer<'font> {
     #[builder(default = (1920, 1080), setter( transform = |width: u32, height: u32| (width, height) ))]
     output_size: (u32, u32),
     #[builder(default = 140.0)]
     font_scale: f32,
-    #[builder(setter(
-        transform = |bold: &'font [u8], light: &'font [u8]| {
-            let bold = Font::try_from_bytes(bold).unwrap_or_else(|| panic!("invalid bold font data"));
-            let light = Font::try_from_bytes(light).unwrap_or_else(|| panic!("invalid light font data"));
-            FontSet {
-                bold, light
-            }
-        }
-    ))]
+    /// Space in pixels between the text and the edges of the quote area.
+    #[builder(default = 30)]
+    gap: u32,
+    /// Space between the text and the edges as a multiple of the quote font size, so it scales
+    /// with the text. Takes precedence over `gap`.
+    #[builder(default, setter(strip_option))]
+    gap_em: Option<f32>,
+    /// Fonts of the quote and the username, see [`FontSet`] for the ways to build it.
     font: FontSet<'font>,
+    #[builder(default)]
+    background_fill: BackgroundFill,
+    /// Draw a rule between the avatar and the quote area. Letter avatars also get their circle
+    /// stroked.
+    #[builder(default, setter(strip_option))]
+    avatar_border: Option<BorderConfig>,
+    /// Colors for the generated letter avatar, picked by `id % colors.len()`. Use the Telegram
+    /// colors when empty.
+    #[builder(default)]
+    letter_avatar_colors: Vec<[u8; 4]>,
+    /// How many words of the name are used as initials for the generated letter avatar.
+    #[builder(default = 1)]
+    letter_avatar_initials: usize,
+    /// The gap between the letter avatar circle and its region edge, as a ratio of the region
+    /// width. Set to 0 to get a full inscribed circle.
+    #[builder(default = 1.0 / 12.0)]
+    letter_avatar_padding_ratio: f32,
+    /// Shrink the letter of the generated letter avatar to this ratio of the circle radius, so
+    /// there is some padding around it. `None` draws the letter at its full scale.
+    #[builder(default = Some(0.8))]
+    letter_avatar_letter_ratio: Option<f32>,
+    /// Fade the rightmost pixels of the avatar over this width, instead of 1/3 of the avatar
+    /// width.
+    #[builder(default, setter(strip_option))]
+    gradient_width_px: Option<u32>,
+    /// Start the fade at this ratio of the avatar width and run it to the right edge, like 0.5 to
+    /// fade the right half. Takes precedence over `gradient_width_px`.
+    #[builder(default, setter(strip_option))]
+    gradient_start_ratio: Option<f32>,
+    /// Fade the avatar into the background with a gradient. Disable it to keep the avatar
+    /// untouched, like on transparent or colored backgrounds.
+    #[builder(default = true)]
+    gradient: bool,
+    /// The alpha where the gradient between avatar and quotes ends. Lower it for a softer blend.
+    #[builder(default = 255)]
+    gradient_max_alpha: u8,
+    /// Add subtle noise over the gradient to reduce banding after JPEG compression.
+    #[builder(default = false)]
+    dither: bool,
+    /// Keep this ratio of the output width for the quote, and scale and crop the avatar to fill
+    /// the rest, instead of sizing the quote area after the avatar.
+    #[builder(default, setter(strip_option))]
+    quote_area_ratio: Option<f32>,
+    /// Blend translucent avatars onto the background color before compositing, so the blur fill
+    /// or a transparent background doesn't show through them.
+    #[builder(default = false)]
+    flatten_avatar: bool,
+    /// Render everything at this many times the output size, then scale it down, for smoother
+    /// text and circle edges on small outputs. 1 renders at the output size directly, up to 4 is
+    /// accepted.
+    #[builder(default = 1)]
+    supersample: u32,
+    /// Fade the alpha of the rightmost pixels of the avatar over this width, to hide the seam
+    /// between the avatar and the quote area. Disabled with 0.
+    #[builder(default = 0)]
+    feather_px: u32,
+    /// Fake the font weights when bold and light text use the same face, like a
+    /// [`FontSet::single`]: the quote is drawn thicker and the username is drawn with lower
+    /// opacity. Two different faces are drawn as they are.
+    #[builder(default = false)]
+    synthetic_weights: bool,
+    /// Extra space in pixels between the quote characters.
+    #[builder(default = 0.0)]
+    letter_spacing: f32,
+    /// Format of the image returned by [`QuoteProducer::make_image`].
+    #[builder(default = ImageFormat::Jpeg)]
+    output_format: ImageFormat,
+    /// Color of the background and the gradient over the avatar. The quote is white and the
+    /// username is grey, so a light background also needs darker text colors to be readable.
+    #[builder(default = [0, 0, 0, 255])]
+    background_color: [u8; 4],
+    /// Leave the background fully transparent. The output format must support alpha channel,
+    /// like `ImageFormat::Png`.
+    #[builder(default = false)]
+    transparent_background: bool,
+    /// Color filter for the whole image, applied after the text is drawn.
+    #[builder(default, setter(strip_option))]
+    output_filter: Option<OutputFilter>,
+    /// Round the corners of the output image, leaving the outside transparent. Like
+    /// `transparent_background`, it requires an output format with alpha channel.
+    #[builder(default, setter(strip_option))]
+    corner_radius: Option<u32>,
+    /// Put the image on a larger transparent canvas with a shadow under it. The output size grows
+    /// by [`DropShadow::margin`] on each side, and the output format must support alpha channel.
+    #[builder(default, setter(strip_option))]
+    drop_shadow: Option<DropShadow>,
+    /// Crop the output to the bounding box of the non-transparent pixels, so the image is
+    /// smaller than the output size. Only useful with `transparent_background`.
+    #[builder(default = false)]
+    trim: bool,
+    /// Remember the wrapped lines of this many recent quotes, so rendering a popular quote again
+    /// skips the text measurement. Disabled by default.
+    #[builder(default, setter( transform = |capacity: usize| components::LineCache::new(capacity) ))]
+    line_cache: components::LineCache,
+    /// Remember the metrics of this many glyphs of the quote font, shared by all renders. `0`
+    /// disables it, the default keeps 8192 glyphs.
+    #[builder(default, setter( transform = |capacity: usize| components::GlyphCache::new(capacity) ))]
+    glyph_cache: components::GlyphCache,
+    /// Filter used to scale the avatar image. `FilterType::Nearest` keeps pixel art sharp.
+    #[builder(default = FilterType::CatmullRom)]
+    resize_filter: FilterType,
+    /// Write the quote and username into the output file: `iTXt` chunks for PNG, a comment
+    /// segment for JPEG. Other formats are written without them.
+    #[builder(default = false)]
+    embed_metadata: bool,
+    /// Write the resolution into the output file, so it is imported at the right physical size:
+    /// a `pHYs` chunk for PNG, the JFIF density for JPEG. Other formats are written without it.
+    #[builder(default, setter(strip_option))]
+    dpi: Option<u32>,
+    /// Lower the JPEG quality until the output fits in this many bytes, like the upload limit of
+    /// a platform. Fails with [`ErrorKind::OutputTooLarge`] if even the lowest quality doesn't
+    /// fit. Other formats are written as is.
+    #[builder(default, setter(strip_option))]
+    max_output_bytes: Option<usize>,
+    /// Chroma subsampling of JPEG output. By default baseline JPEG keeps the full color
+    /// resolution, and progressive JPEG halves it in both directions below quality 90.
+    #[builder(default, setter(strip_option))]
+    chroma_subsampling: Option<ChromaSubsampling>,
+    /// Write PNG and JPEG with encoder settings pinned by this crate, instead of the defaults of
+    /// the `image` encoders which may change between its versions. The same inputs then give
+    /// byte-identical output with the same library versions on the same platform. PNG rows all
+    /// use the `Sub` filter, JPEG keeps the full color resolution unless `chroma_subsampling` is
+    /// set, other formats are written by `image` as is.
+    #[builder(default = false)]
+    deterministic: bool,
+    /// Encode JPEG output as progressive JPEG, which loads from blurry to sharp on the web.
+    #[builder(default = false)]
+    progressive_jpeg: bool,
+    /// Draw the username above or below the quote.
+    #[builder(default)]
+    username_position: Vertical,
+    #[builder(default)]
+    username_align: Horizontal,
+    /// Show at most this many lines of the quote, and end the last one with an ellipsis if the
+    /// quote is cut.
+    #[builder(default, setter(strip_option))]
+    max_lines: Option<usize>,
+    /// Ends the last line of a quote cut by `max_lines`, like `...` or `⋯`.
+    #[builder(default = "\u{2026}".to_string(), setter( transform = |s: impl Into<String>| s.into() ))]
+    ellipsis: String,
+    /// When the quote overflows, first narrow the avatar down to this ratio of its width to
+    /// widen the quote column, then shrink the quote font until it fits. The avatar is cropped
+    /// evenly at both sides.
+    #[builder(default, setter(strip_option))]
+    fit_long_quote: Option<f32>,
+    /// Draw a translucent rounded panel behind the quote, for contrast without a full gradient.
+    #[builder(default, setter(strip_option))]
+    text_panel: Option<PanelConfig>,
+    /// Move the CJK comma and full stop marks like `、` and `。` down by this ratio of the quote
+    /// font size, for fonts that put them too high next to full-width chars.
+    #[builder(default, setter(strip_option))]
+    cjk_punctuation_offset: Option<f32>,
+    /// Pick black or white quote text from the average luminance behind the quote, instead of
+    /// always white. Explicit span colors are kept.
+    #[builder(default = false)]
+    auto_text_color: bool,
+    /// Draw the username on a rounded colored chip, like a role badge in chat apps.
+    #[builder(default, setter(strip_option))]
+    username_chip: Option<ChipConfig>,
+    /// Fill the quote glyphs with a vertical gradient from the first color to the second,
+    /// instead of the flat quote color. Colored spans are tinted by it.
+    #[builder(default, setter(strip_option))]
+    quote_gradient: Option<(Rgba<u8>, Rgba<u8>)>,
+    /// Called with the label and the canvas after each compositing step: `background`, `avatar`,
+    /// `gradient` and `quotes`. The avatar steps are skipped without an avatar, and the canvas is
+    /// at the supersampled size.
+    #[cfg(feature = "debug-steps")]
+    #[builder(default, setter(strip_option))]
+    step_hook: Option<StepHook>,
+    /// Vertical placement of the quote, it is centered by default.
+    #[builder(default)]
+    quote_vertical: QuoteVertical,
+    /// Expand tabs, unify line breaks and drop invisible control chars in the text before
+    /// rendering. See [`components::normalize_text`].
+    #[builder(default = true)]
+    normalize_text: bool,
+}
+
+/// Output size and text metrics that suit the image sizes of some platforms.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Platform {
+    /// 1920x1080, the default size of the producer.
+    Telegram,
+    /// 1200x675, the 16:9 size shown uncropped in the timeline.
+    Twitter,
+    /// 1080x1080 square post.
+    Instagram,
+    /// 512x512, the largest static sticker.
+    DiscordSticker,
+}
+
+impl Platform {
+    pub fn output_size(self) -> (u32, u32) {
+        match self {
+            Platform::Telegram => (1920, 1080),
+            Platform::Twitter => (1200, 675),
+            Platform::Instagram => (1080, 1080),
+            Platform::DiscordSticker => (512, 512),
+        }
+    }
+
+    pub fn font_scale(self) -> f32 {
+        match self {
+            Platform::Telegram => 140.0,
+            Platform::Twitter => 88.0,
+            Platform::Instagram => 80.0,
+            Platform::DiscordSticker => 40.0,
+        }
+    }
+
+    /// Space between the text and the edges of the quote area.
+    pub fn gap(self) -> u32 {
+        match self {
+            Platform::Telegram => 30,
+            Platform::Twitter => 20,
+            Platform::Instagram => 24,
+            Platform::DiscordSticker => 12,
+        }
+    }
+}
+
+/// Decide how to fill the avatar region when the avatar can't cover it.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BackgroundFill {
+    /// Leave the uncovered area with the background color.
+    #[default]
+    Solid,
+    /// Fill the avatar region with a heavily blurred, scaled-up copy of the avatar.
+    BlurAvatar,
+}
+
+/// How the color of JPEG output is sampled, relative to the brightness.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChromaSubsampling {
+    /// Full color resolution, the sharpest edges on colored text.
+    Yuv444,
+    /// Half the color resolution horizontally.
+    Yuv422,
+    /// Half the color resolution in both directions, the smallest files.
+    Yuv420,
+}
+
+// The texts of a config, prepared for the quote column
+struct QuoteColumn<'f> {
+    quote: String,
+    quote_styles: Vec<components::CharStyle<'f>>,
+    color: [u8; 4],
+    username: String,
+    handle: Option<String>,
+    subtitle: Option<String>,
+    username_icon: Option<RgbaImage>,
+    bg_dim: (u32, u32),
 }
 
+/// Parsed fonts for each text role. The roles without their own font fallback to the bold or
+/// light font.
+#[derive(Clone)]
 pub struct FontSet<'font> {
     bold: Font<'font>,
     light: Font<'font>,
+    quote: Option<Font<'font>>,
+    username: Option<Font<'font>>,
+    accent: Option<Font<'font>>,
+    // The bold and light fonts are the same face, so their weights look the same
+    same_face: bool,
+}
+
+impl<'font> FontSet<'font> {
+    /// Parse the bold and light font data, the first face of each font collection is used.
+    pub fn new(bold: &'font [u8], light: &'font [u8]) -> Result<Self> {
+        Self::with_indices(bold, 0, light, 0)
+    }
+
+    /// Parse the given face of the bold and light fonts, for font collections like `.ttc` files.
+    pub fn with_indices(
+        bold: &'font [u8],
+        bold_index: u32,
+        light: &'font [u8],
+        light_index: u32,
+    ) -> Result<Self> {
+        if bold == light && bold_index == light_index {
+            return Self::single(bold, bold_index);
+        }
+        let bold = Font::try_from_bytes_and_index(bold, bold_index)
+            .ok_or(ErrorKind::InvalidFont("invalid bold font data"))?;
+        let light = Font::try_from_bytes_and_index(light, light_index)
+            .ok_or(ErrorKind::InvalidFont("invalid light font data"))?;
+        Ok(Self::from_fonts(bold, light))
+    }
+
+    /// Use one face of the font for both the bold and the light text, the data is parsed once.
+    pub fn single(data: &'font [u8], index: u32) -> Result<Self> {
+        let font = Font::try_from_bytes_and_index(data, index)
+            .ok_or(ErrorKind::InvalidFont("invalid font data"))?;
+        Ok(Self {
+            same_face: true,
+            ..Self::from_fonts(font.clone(), font)
+        })
+    }
+
+    /// Use fonts which are already parsed, like the ones shared with other crates.
+    pub fn from_fonts(bold: Font<'font>, light: Font<'font>) -> Self {
+        Self {
+            bold,
+            light,
+            quote: None,
+            username: None,
+            accent: None,
+            same_face: false,
+        }
+    }
+
+    /// Draw the quote text with this font, instead of the bold font. Parse it with
+    /// [`Font::try_from_bytes_and_index`] to pick a face of a font collection.
+    pub fn quote_font(self, font: Font<'font>) -> Self {
+        Self {
+            quote: Some(font),
+            ..self
+        }
+    }
+
+    /// Draw the username with this font, instead of the light font.
+    pub fn username_font(self, font: Font<'font>) -> Self {
+        Self {
+            username: Some(font),
+            ..self
+        }
+    }
+
+    /// Draw the `*bold*` text with this font when `parse_markdown` is on, instead of the bold
+    /// font.
+    pub fn accent_font(self, font: Font<'font>) -> Self {
+        Self {
+            accent: Some(font),
+            ..self
+        }
+    }
+
+    /// Font of the quote text, fallback to the bold font.
+    pub fn quote(&self) -> &Font<'font> {
+        self.quote.as_ref().unwrap_or(&self.bold)
+    }
+
+    /// Font of the username, fallback to the light font.
+    pub fn username(&self) -> &Font<'font> {
+        self.username.as_ref().unwrap_or(&self.light)
+    }
+
+    /// Font of the `*bold*` text in markdown quotes, fallback to the bold font.
+    pub fn accent(&self) -> &Font<'font> {
+        self.accent.as_ref().unwrap_or(&self.bold)
+    }
+
+    /// The chars in the text that some of the fonts have no glyph for, without duplicates.
+    /// Whitespace is skipped as it is never drawn.
+    pub fn uncovered(&self, text: &str) -> Vec<char> {
+        let fonts = [
+            &self.bold,
+            &self.light,
+            self.quote(),
+            self.username(),
+            self.accent(),
+        ];
+        let mut missing = Vec::new();
+        for c in text.chars() {
+            let lacks = fonts.iter().any(|font| font.glyph(c).id().0 == 0);
+            if !c.is_whitespace() && lacks && !missing.contains(&c) {
+                missing.push(c);
+            }
+        }
+        missing
+    }
+
+    /// Whether all the fonts can draw every char in the text.
+    pub fn covers(&self, text: &str) -> bool {
+        self.uncovered(text).is_empty()
+    }
 }
 
 pub enum SpooledData<'data> {
     InMem(&'data [u8]),
     OnDisk(&'data Path),
-    TgRandom { id: u64, name: String },
+    /// An image already decoded by the caller, it is used without encoding it again. The
+    /// `AsRef<Path>` blanket impl rules out `AsSpooledData` for `DynamicImage` itself, so pass
+    /// this variant to [`ImgConfigBuilder::avatar`].
+    Decoded(&'data DynamicImage),
+    /// Image file data owned by the config, like the content read by
+    /// [`SpooledData::from_reader`].
+    Owned(Vec<u8>),
+    TgRandom {
+        id: u64,
+        name: String,
+    },
+    /// Generate an avatar showing the first character of the text on a colored circle. The circle
+    /// color is derived from the text when `color` is `None`.
+    Initials {
+        text: String,
+        color: Option<[u8; 4]>,
+    },
 }
 
 pub trait AsSpooledData {
@@ -106,106 +527,1016 @@ impl AsSpooledData for [u8] {
     }
 }
 
+impl SpooledData<'_> {
+    /// Read the whole image file from a stream, like a network body or an archive entry. The
+    /// format is guessed from the content when the avatar is decoded.
+    pub fn from_reader(reader: &mut dyn Read) -> Result<Self> {
+        let mut buffer = Vec::new();
+        reader
+            .read_to_end(&mut buffer)
+            .map_err(|err| ErrorKind::AvatarDecode(ImageError::IoError(err)))?;
+        Ok(SpooledData::Owned(buffer))
+    }
+
+    /// Create a Telegram style letter avatar without a numeric id. The color is picked by a stable
+    /// hash of the name, so the same name always gets the same color, even across runs.
+    pub fn tg_from_name(name: impl Display) -> Self {
+        let name = name.to_string();
+        SpooledData::TgRandom {
+            id: components::stable_hash(&name),
+            name,
+        }
+    }
+}
+
 impl<'data> AsSpooledData for SpooledData<'data> {
     fn as_spooled_data(&self) -> SpooledData<'_> {
         match self {
             SpooledData::InMem(m) => SpooledData::InMem(m),
             SpooledData::OnDisk(d) => SpooledData::OnDisk(d),
+            SpooledData::Decoded(img) => SpooledData::Decoded(img),
+            SpooledData::Owned(buffer) => SpooledData::InMem(buffer),
             SpooledData::TgRandom { id, name } => SpooledData::TgRandom {
                 id: *id,
                 name: name.to_string(),
             },
+            SpooledData::Initials { text, color } => SpooledData::Initials {
+                text: text.to_string(),
+                color: *color,
+            },
         }
     }
 }
 
 #[derive(TypedBuilder)]
 pub struct ImgConfig<'a> {
-    #[builder(setter( transform = |s: impl Display| s.to_string() ))]
-    quote: String,
+    /// The quote text. Rendering fails with [`ErrorKind::MissingField`] when neither it nor
+    /// `quote_spans` is set.
+    #[builder(default, setter( transform = |s: impl Display| Some(s.to_string()) ))]
+    quote: Option<String>,
+    /// Give the quote as colored pieces of text, it replaces `quote` when set.
+    #[builder(default, setter( transform = |spans: impl IntoIterator<Item = (impl Display, [u8; 4])>| {
+        spans.into_iter().map(|(s, color)| (s.to_string(), color)).collect()
+    }))]
+    quote_spans: Vec<(String, [u8; 4])>,
+    /// Draw `*bold*` text with the bold font, and other text with the light font.
+    #[builder(default = false)]
+    parse_markdown: bool,
     #[builder(setter( transform = |s: impl Display| s.to_string() ))]
     username: String,
-    #[builder(setter( transform = |p: &'a (impl AsSpooledData + ?Sized)| p.as_spooled_data() ))]
-    avatar: SpooledData<'a>,
+    /// Show this handle with a `@` prefix under the username, which is then used as the display
+    /// name.
+    #[builder(default, setter( transform = |s: impl Display| Some(s.to_string()) ))]
+    handle: Option<String>,
+    /// Smaller text between the quote and the username, like the context or the source.
+    #[builder(default, setter( transform = |s: impl Display| Some(s.to_string()) ))]
+    subtitle: Option<String>,
+    /// Optional, the quote takes the whole image when there is no avatar.
+    #[builder(default, setter( transform = |p: &'a (impl AsSpooledData + ?Sized)| Some(p.as_spooled_data()) ))]
+    avatar: Option<SpooledData<'a>>,
+    /// Small image drawn right before the username, like a verified badge. It is scaled to the
+    /// cap height of the username.
+    #[builder(default, setter( transform = |p: &'a (impl AsSpooledData + ?Sized)| Some(p.as_spooled_data()) ))]
+    username_icon: Option<SpooledData<'a>>,
+    #[builder(default)]
+    avatar_filter: AvatarFilter,
+    /// Which part of the avatar is kept when it is cropped to fit the avatar region.
+    #[builder(default)]
+    crop_anchor: CropAnchor,
+    /// Crop this `(x, y, width, height)` region of the avatar image, in source pixels, instead of
+    /// cropping by `crop_anchor`. Letter avatars ignore it.
+    #[builder(default, setter(strip_option))]
+    avatar_crop_rect: Option<(u32, u32, u32, u32)>,
+    /// Magnify the avatar in its slot, like 1.5 to show the middle 2/3 of it. Letter avatars
+    /// ignore it.
+    #[builder(default = 1.0)]
+    avatar_zoom: f32,
+    /// Pan the zoomed avatar by `(x, y)` pixels to bring an off-center face into view. It can't
+    /// move past the edges of the zoomed avatar.
+    #[builder(default)]
+    avatar_offset: (i32, i32),
+    /// Scale the avatar by its exact aspect ratio before cropping it, instead of the whole number
+    /// ratio which squeezes avatars like 4:3 into a square.
+    #[builder(default = false)]
+    keep_avatar_aspect_ratio: bool,
+}
+
+impl<'a> ImgConfig<'a> {
+    /// Build a config from pieces which may be missing at runtime, like fields parsed from JSON.
+    /// The other options keep their defaults.
+    pub fn try_from_parts(
+        quote: Option<String>,
+        username: Option<String>,
+        avatar: Option<SpooledData<'a>>,
+    ) -> Result<Self> {
+        let quote = quote.ok_or(ErrorKind::MissingField("quote"))?;
+        let username = username.ok_or(ErrorKind::MissingField("username"))?;
+        let mut config = Self::builder().quote(quote).username(username).build();
+        config.avatar = avatar;
+        Ok(config)
+    }
+
+    // The plain quote text, without any style
+    fn quote_text(&self) -> String {
+        if self.quote_spans.is_empty() {
+            self.quote.clone().unwrap_or_default()
+        } else {
+            self.quote_spans
+                .iter()
+                .map(|(text, _)| text.as_str())
+                .collect()
+        }
+    }
+}
+
+// Black on bright backgrounds and white on dark ones, from the average luminance right to `x`.
+// Transparent pixels count as dark.
+fn contrast_color(img: &RgbaImage, x: u32) -> [u8; 4] {
+    let (mut sum, mut count) = (0.0, 0u64);
+    for (_, _, pixel) in img.enumerate_pixels().filter(|(px, _, _)| *px >= x) {
+        let [r, g, b, a] = pixel.0.map(|c| c as f64);
+        sum += (0.2126 * r + 0.7152 * g + 0.0722 * b) * a / 255.0;
+        count += 1;
+    }
+    if count > 0 && sum / count as f64 > 127.5 {
+        [0, 0, 0, 255]
+    } else {
+        [255, 255, 255, 255]
+    }
+}
+
+// Crop and resize the avatar decoded from an image. The offset in the config is in output pixels
+// and is scaled by `ss` like the background.
+fn photo_avatar(
+    img_data: RgbaImage,
+    config: &ImgConfig,
+    ss: u32,
+    bg_height: u32,
+    region: Option<(u32, u32)>,
+    max_width: Option<u32>,
+    resize_filter: FilterType,
+) -> Result<RgbaImage> {
+    if let Some((x, y, width, height)) = config.avatar_crop_rect {
+        let (img_w, img_h) = img_data.dimensions();
+        let inside = width > 0
+            && height > 0
+            && x.checked_add(width).is_some_and(|right| right <= img_w)
+            && y.checked_add(height).is_some_and(|bottom| bottom <= img_h);
+        if !inside {
+            return Err(ErrorKind::InvalidCropRect {
+                rect: (x, y, width, height),
+                avatar: (img_w, img_h),
+            });
+        }
+    }
+
+    Ok(components::Avatar::builder()
+        .img_data(img_data)
+        .bg_height(bg_height)
+        .filter(config.avatar_filter)
+        .crop_anchor(config.crop_anchor)
+        .crop_rect(config.avatar_crop_rect)
+        .region(region)
+        .zoom(config.avatar_zoom)
+        .offset((
+            config.avatar_offset.0.saturating_mul(ss as i32),
+            config.avatar_offset.1.saturating_mul(ss as i32),
+        ))
+        .keep_aspect_ratio(config.keep_avatar_aspect_ratio)
+        .max_width(max_width)
+        .resize_filter(resize_filter)
+        .build())
+}
+
+/// Render a Telegram style letter avatar as a standalone `size`x`size` PNG, with the circle color
+/// picked from the name like [`SpooledData::tg_from_name`]. The letter is shrunk to
+/// `letter_ratio` of the circle radius, like `letter_avatar_letter_ratio` of the producer.
+pub fn make_letter_avatar(
+    name: &str,
+    size: u32,
+    font: &[u8],
+    letter_ratio: Option<f32>,
+) -> Result<Vec<u8>> {
+    if size == 0 {
+        return Err(ErrorKind::InvalidDimensions {
+            width: size,
+            height: size,
+            reason: "avatar size must be non-zero",
+        });
+    }
+    // 4 bytes per RGBA pixel
+    if size
+        .checked_mul(size)
+        .and_then(|n| n.checked_mul(4))
+        .is_none()
+    {
+        return Err(ErrorKind::InvalidDimensions {
+            width: size,
+            height: size,
+            reason: "avatar size is too large",
+        });
+    }
+    let font = Font::try_from_bytes(font).ok_or(ErrorKind::InvalidFont("invalid font data"))?;
+
+    let letter = components::initials(name, &font, 1);
+    let info = components::TextDrawInfo::builder()
+        .text(&letter)
+        .rgba([255, 255, 255, 255])
+        .scale(size as f32 / 2.0)
+        .font(&font)
+        .build();
+    let avatar: RgbaImage = components::TgAvatar::builder()
+        .id(components::stable_hash(name))
+        .info(info)
+        .size((size, size))
+        .letter_ratio(letter_ratio)
+        .build();
+
+    let mut buffer = Cursor::new(Vec::new());
+    avatar.write_to(&mut buffer, ImageFormat::Png)?;
+    Ok(buffer.into_inner())
 }
 
 impl<'font> QuoteProducer<'font> {
+    /// Create a producer with the output size, font scale and gap of the platform. To override
+    /// some of them, start from [`QuoteProducer::builder`] and take the rest from [`Platform`].
+    pub fn preset(platform: Platform, font: FontSet<'font>) -> Self {
+        let (width, height) = platform.output_size();
+        Self::builder()
+            .font(font)
+            .output_size(width, height)
+            .font_scale(platform.font_scale())
+            .gap(platform.gap())
+            .build()
+    }
+
+    /// The width and height of the generated images.
+    pub fn output_size(&self) -> (u32, u32) {
+        self.output_size
+    }
+
+    /// Width of the avatar region in the image generated for the config, where the quote column
+    /// starts, or 0 without an avatar. It covers the blur fill of [`BackgroundFill::BlurAvatar`].
+    /// The avatar is loaded, cropped and narrowed for `fit_long_quote` like
+    /// [`QuoteProducer::make_image`] does, but nothing is drawn.
+    pub fn avatar_region_width(&self, config: &ImgConfig) -> Result<u32> {
+        let Some(data) = &config.avatar else {
+            return Ok(0);
+        };
+        let ss = self.supersample;
+        let bg_dim = self.render_size(self.output_size)?;
+        let avatar = self.load_avatar(data, config, &self.font, bg_dim)?;
+        // The quote color and the icon don't change how the text is wrapped
+        let column = self.quote_column(config, bg_dim, [255, 255, 255, 255], None);
+        let (avatar_width, _) = self.fit_quotes(&column, avatar.width());
+        Ok(self.avatar_region(avatar_width, bg_dim.0) / ss)
+    }
+
+    /// Generate the quote image and encode it into the output format, JPEG by default.
     pub fn make_image(&self, config: &ImgConfig) -> Result<Vec<u8>> {
+        self.encode_image(config, None)
+    }
+
+    /// Like [`QuoteProducer::make_image`], and also tell how the quote is laid out, like whether
+    /// it is too long to fit in the image, or some chars can't be drawn with the font.
+    pub fn make_image_with_report(&self, config: &ImgConfig) -> Result<(Vec<u8>, QuoteReport)> {
+        let report = Cell::default();
+        let buffer = self.encode_image(config, Some(&report))?;
+        Ok((buffer, report.take()))
+    }
+
+    /// Like [`QuoteProducer::make_image`], and also return the MIME type of the output format,
+    /// like `image/jpeg`, for the `Content-Type` header.
+    pub fn make_image_with_mime(&self, config: &ImgConfig) -> Result<(Vec<u8>, &'static str)> {
+        let buffer = self.encode_image(config, None)?;
+        Ok((buffer, self.output_format.to_mime_type()))
+    }
+
+    fn encode_image(
+        &self,
+        config: &ImgConfig,
+        report: Option<&Cell<QuoteReport>>,
+    ) -> Result<Vec<u8>> {
+        self.check_alpha()?;
+        let background = self.render(config, self.output_size, report)?;
+        self.encode(config, &background)
+    }
+
+    /// Generate the quote image at each of the sizes, like for 1x, 2x and 3x screens. The
+    /// pipeline runs once at the largest size, and the other sizes with the same aspect ratio
+    /// are downscaled from it with the resize filter, so they look the same. Sizes of another
+    /// aspect ratio are rendered on their own. The output size of the producer is not used.
+    ///
+    /// Like in [`QuoteProducer::make_image`], `trim` and `drop_shadow` change the size of the
+    /// images. The downscaled ones keep the aspect ratio of the largest image instead of being
+    /// stretched to the requested size.
+    pub fn make_image_multi(
+        &self,
+        config: &ImgConfig,
+        sizes: &[(u32, u32)],
+    ) -> Result<Vec<Vec<u8>>> {
+        self.check_alpha()?;
+        let Some(&largest) = sizes.iter().max_by_key(|(w, h)| *w as u64 * *h as u64) else {
+            return Ok(Vec::new());
+        };
+        let full = self.render(config, largest, None)?;
+        sizes
+            .iter()
+            .map(|&(width, height)| {
+                // Allow the sizes to be off by the rounding of the scaled dimensions
+                let skew =
+                    (width as u64 * largest.1 as u64).abs_diff(height as u64 * largest.0 as u64);
+                let image = if (width, height) == largest {
+                    full.clone()
+                } else if width > 0 && height > 0 && skew <= largest.0.max(largest.1) as u64 {
+                    let scaled = |full: u32, size: u32, largest: u32| {
+                        let scaled = full as f64 * size as f64 / largest as f64;
+                        (scaled.round() as u32).max(1)
+                    };
+                    let width = scaled(full.width(), width, largest.0);
+                    let height = scaled(full.height(), height, largest.1);
+                    imageops::resize(&full, width, height, self.resize_filter)
+                } else {
+                    self.render(config, (width, height), None)?
+                };
+                self.encode(config, &image)
+            })
+            .collect()
+    }
+
+    fn check_alpha(&self) -> Result<()> {
+        let keeps_alpha = !matches!(self.output_format, ImageFormat::Jpeg | ImageFormat::Pnm);
+        let needs_alpha = self.transparent_background
+            || self.corner_radius.is_some()
+            || self.drop_shadow.is_some();
+        if needs_alpha && !keeps_alpha {
+            return Err(ErrorKind::TransparencyUnsupported(self.output_format));
+        }
+        Ok(())
+    }
+
+    fn encode(&self, config: &ImgConfig, background: &RgbaImage) -> Result<Vec<u8>> {
+        let pinned_png = self.embed_metadata || self.dpi.is_some() || self.deterministic;
+        if pinned_png && self.output_format == ImageFormat::Png {
+            let quote = config.quote_text();
+            let text = self
+                .embed_metadata
+                .then_some((quote.as_str(), config.username.as_str()));
+            return Ok(encode::write_png(background, text, self.dpi)?);
+        }
+
+        if self.output_format != ImageFormat::Jpeg {
+            let mut buffer = Cursor::new(Vec::new());
+            background.write_to(&mut buffer, self.output_format)?;
+            return Ok(buffer.into_inner());
+        }
+        let Some(limit) = self.max_output_bytes else {
+            // Same quality as the `image` JPEG encoder
+            return self.encode_jpeg(config, background, 75);
+        };
+
+        // Nothing fits if the lowest quality doesn't
+        let mut best = self.encode_jpeg(config, background, 1)?;
+        if best.len() > limit {
+            return Err(ErrorKind::OutputTooLarge {
+                size: best.len(),
+                limit,
+            });
+        }
+        // Search the highest quality that fits, the size grows with the quality
+        let (mut low, mut high) = (2, 100);
+        while low <= high {
+            let quality = (low + high) / 2;
+            let buffer = self.encode_jpeg(config, background, quality)?;
+            if buffer.len() <= limit {
+                best = buffer;
+                low = quality + 1;
+            } else {
+                high = quality - 1;
+            }
+        }
+        Ok(best)
+    }
+
+    fn encode_jpeg(
+        &self,
+        config: &ImgConfig,
+        background: &RgbaImage,
+        quality: u8,
+    ) -> Result<Vec<u8>> {
+        let buffer =
+            if self.deterministic || self.progressive_jpeg || self.chroma_subsampling.is_some() {
+                let sampling = self
+                    .chroma_subsampling
+                    .or(self.deterministic.then_some(ChromaSubsampling::Yuv444));
+                encode::write_jpeg(background, quality, self.progressive_jpeg, sampling)?
+            } else {
+                let mut buffer = Vec::new();
+                JpegEncoder::new_with_quality(&mut buffer, quality).encode_image(background)?;
+                buffer
+            };
+        let buffer = match self.dpi {
+            Some(dpi) => encode::set_jpeg_density(buffer, dpi),
+            None => buffer,
+        };
+
+        if self.embed_metadata {
+            let comment = format!("{}\n-- {}", config.quote_text(), config.username);
+            return Ok(encode::insert_jpeg_comment(buffer, &comment));
+        }
+        Ok(buffer)
+    }
+
+    /// Generate the quote image without encoding it, so you can post-process the pixels.
+    pub fn make_raw_image(&self, config: &ImgConfig) -> Result<RgbaImage> {
+        self.render(config, self.output_size, None)
+    }
+
+    /// Composite the quote image into `canvas` with its top left corner at `(x, y)`. Parts
+    /// outside of the canvas are clipped, and a transparent background keeps the canvas visible.
+    pub fn render_onto(
+        &self,
+        config: &ImgConfig,
+        canvas: &mut RgbaImage,
+        x: i64,
+        y: i64,
+    ) -> Result<()> {
+        let card = self.render(config, self.output_size, None)?;
+        imageops::overlay(canvas, &card, x, y);
+        Ok(())
+    }
+
+    // The size of the canvas everything is drawn on, `supersample` times the output size
+    fn render_size(&self, (width, height): (u32, u32)) -> Result<(u32, u32)> {
+        if !(1..=4).contains(&self.supersample) {
+            return Err(ErrorKind::InvalidDimensions {
+                width,
+                height,
+                reason: "supersample must be between 1 and 4",
+            });
+        }
+        let scaled = |size: u32| size.checked_mul(self.supersample);
+        match (scaled(width), scaled(height)) {
+            (Some(width), Some(height)) => Ok((width, height)),
+            _ => Err(ErrorKind::InvalidDimensions {
+                width,
+                height,
+                reason: "output size is too large to supersample",
+            }),
+        }
+    }
+
+    fn render(
+        &self,
+        config: &ImgConfig,
+        (width, height): (u32, u32),
+        report: Option<&Cell<QuoteReport>>,
+    ) -> Result<RgbaImage> {
+        if width == 0 || height == 0 {
+            return Err(ErrorKind::InvalidDimensions {
+                width,
+                height,
+                reason: "output size must be non-zero",
+            });
+        }
+        if config.quote.is_none() && config.quote_spans.is_empty() {
+            return Err(ErrorKind::MissingField("quote"));
+        }
+
+        let fonts = &self.font;
+        let render_size = self.render_size((width, height))?;
+
+        let background_color = if self.transparent_background {
+            Rgba([0, 0, 0, 0])
+        } else {
+            Rgba(self.background_color)
+        };
+        // Pixel sizes are scaled along with the canvas when supersampling
+        let ss = self.supersample;
         let mut background = components::Background::builder()
-            .output_dimension(self.output_size)
+            .output_dimension(render_size)
+            .color(background_color)
             .build();
+        self.step("background", &background);
 
-        // Step 1: Overlay avatar to background
+        // Step 1: Load the avatar, it is drawn once the width of the quote column is settled
         let avatar = match &config.avatar {
+            Some(data) => {
+                let mut avatar = self.load_avatar(data, config, fonts, background.dimensions())?;
+                if avatar.width() >= background.width() {
+                    return Err(ErrorKind::InvalidDimensions {
+                        width,
+                        height,
+                        reason: "avatar is wider than the output image",
+                    });
+                }
+                if self.flatten_avatar {
+                    components::flatten(&mut avatar, Rgba(self.background_color));
+                }
+                Some(avatar)
+            }
+            None => None,
+        };
+        let username_icon = config
+            .username_icon
+            .as_ref()
+            .map(|data| self.load_icon(data, fonts))
+            .transpose()?;
+
+        // Step 2: Lay out the quotes, the color doesn't change the layout
+        let quote_color = [255, 255, 255, 255];
+        let mut column =
+            self.quote_column(config, background.dimensions(), quote_color, username_icon);
+        let avatar_width = avatar.as_ref().map_or(0, |avatar| avatar.width());
+        let (avatar_width, quote_scale) = self.fit_quotes(&column, avatar_width);
+        // The quote column starts right to the blur fill, which may be wider than the avatar
+        let region_width = self.avatar_region(avatar_width, background.width());
+
+        if let Some(mut avatar) = avatar {
+            if avatar_width < avatar.width() {
+                let x = (avatar.width() - avatar_width) / 2;
+                avatar =
+                    imageops::crop_imm(&avatar, x, 0, avatar_width, avatar.height()).to_image();
+            }
+            components::feather(&mut avatar, self.feather_px * ss);
+            self.draw_avatar(&mut background, &avatar, region_width);
+        }
+        if self.auto_text_color {
+            column.color = contrast_color(&background, region_width);
+        }
+        let quotes = self.draw_quotes(&column, region_width, quote_scale, report, false);
+        imageops::overlay(&mut background, &quotes, region_width as i64, 0);
+        self.step("quotes", &background);
+        if ss > 1 {
+            background = imageops::resize(&background, width, height, FilterType::Triangle);
+        }
+
+        // Step 3: Filter, cut the corners and cast the shadow after everything is composited
+        if let Some(filter) = self.output_filter {
+            background = filter.apply(background);
+        }
+        if let Some(radius) = self.corner_radius {
+            components::round_corners(&mut background, radius);
+        }
+        if let Some(shadow) = &self.drop_shadow {
+            background = components::drop_shadow(&background, shadow)?;
+        }
+        if self.trim {
+            background = components::trim(background);
+        }
+
+        Ok(background)
+    }
+
+    // Prepare the texts of the config for the quote column
+    fn quote_column(
+        &self,
+        config: &ImgConfig,
+        bg_dim: (u32, u32),
+        color: [u8; 4],
+        username_icon: Option<RgbaImage>,
+    ) -> QuoteColumn<'_> {
+        let (quote, quote_styles) = self.quote_styles(config, &self.font, color);
+        let handle = config.handle.as_ref().map(|handle| {
+            let handle = self.normalized(handle);
+            if handle.starts_with('@') {
+                handle
+            } else {
+                format!("@{handle}")
+            }
+        });
+        QuoteColumn {
+            quote,
+            quote_styles,
+            color,
+            username: self.normalized(&config.username),
+            handle,
+            subtitle: config.subtitle.as_deref().map(|text| self.normalized(text)),
+            username_icon,
+            bg_dim,
+        }
+    }
+
+    // Lay out the quote column right to the avatar, and draw it unless only measuring
+    fn draw_quotes(
+        &self,
+        column: &QuoteColumn,
+        avatar_width: u32,
+        quote_scale: f32,
+        report: Option<&Cell<QuoteReport>>,
+        measure_only: bool,
+    ) -> RgbaImage {
+        let fonts = &self.font;
+        let ss = self.supersample;
+        let synthetic_weights = self.synthetic_weights && fonts.same_face;
+        let quote_info = components::TextDrawInfo::builder()
+            .text(&column.quote)
+            .rgba(column.color)
+            .scale(quote_scale)
+            .font(fonts.quote())
+            .letter_spacing(self.letter_spacing * ss as f32)
+            .build()
+            .with_glyph_cache(&self.glyph_cache);
+        let user_info = components::TextDrawInfo::builder()
+            .text(&column.username)
+            .rgba([147, 147, 147, if synthetic_weights { 153 } else { 255 }])
+            .scale(self.font_scale * ss as f32 / 1.5)
+            .font(fonts.username())
+            .build();
+        let handle_info = column.handle.as_ref().map(|handle| {
+            components::TextDrawInfo::builder()
+                .text(handle)
+                .rgba([100, 100, 100, 255])
+                .scale(self.font_scale * ss as f32 / 2.0)
+                .font(fonts.username())
+                .build()
+        });
+        let subtitle_info = column.subtitle.as_ref().map(|subtitle| {
+            components::TextDrawInfo::builder()
+                .text(subtitle)
+                .rgba([200, 200, 200, 255])
+                .scale(self.font_scale * ss as f32 / 1.25)
+                .font(fonts.username())
+                .build()
+        });
+        let gap = match self.gap_em {
+            Some(em) => (em * quote_scale).round().max(0.0) as u32,
+            None => self.gap * ss,
+        };
+        components::Quotes::builder()
+            .gap(gap)
+            .avatar_width(avatar_width)
+            .bg_dim(column.bg_dim)
+            .quote_info(quote_info)
+            .user_info(user_info)
+            .handle_info(handle_info)
+            .faux_bold(synthetic_weights)
+            .quote_styles(&column.quote_styles)
+            .line_cache(&self.line_cache)
+            .report(report)
+            .username_position(self.username_position)
+            .username_align(self.username_align)
+            .max_lines(self.max_lines)
+            .ellipsis(&self.ellipsis)
+            .quote_vertical(match self.quote_vertical {
+                QuoteVertical::Center => QuoteVertical::Center,
+                QuoteVertical::FromTop(y) => QuoteVertical::FromTop(y * ss),
+                QuoteVertical::FromBottom(y) => QuoteVertical::FromBottom(y * ss),
+            })
+            .subtitle_info(subtitle_info)
+            .punctuation_offset(self.cjk_punctuation_offset)
+            .username_chip(self.username_chip.map(|chip| ChipConfig {
+                padding: chip.padding * ss,
+                corner_radius: chip.corner_radius * ss,
+                ..chip
+            }))
+            .text_panel(self.text_panel.map(|panel| PanelConfig {
+                corner_radius: panel.corner_radius * ss,
+                padding: panel.padding * ss,
+                ..panel
+            }))
+            .username_icon(column.username_icon.as_ref())
+            .quote_gradient(self.quote_gradient)
+            .measure_only(measure_only)
+            .build()
+    }
+
+    // Narrow the avatar by 1/8 at a time, then shrink the font by 10% at a time, until the quote
+    // fits. Only the wrapped lines are measured, nothing is drawn.
+    fn fit_quotes(&self, column: &QuoteColumn, avatar_width: u32) -> (u32, f32) {
+        let mut avatar_width = avatar_width;
+        let mut quote_scale = self.font_scale * self.supersample as f32;
+        let Some(ratio) = self.fit_long_quote else {
+            return (avatar_width, quote_scale);
+        };
+        let overflows = |avatar_width, quote_scale| {
+            let report = Cell::default();
+            let region_width = self.avatar_region(avatar_width, column.bg_dim.0);
+            self.draw_quotes(column, region_width, quote_scale, Some(&report), true);
+            report.take().overflowed
+        };
+        let min_width = (avatar_width as f32 * ratio.clamp(0.0, 1.0)).round() as u32;
+        let step = (avatar_width / 8).max(1);
+        while avatar_width > min_width && overflows(avatar_width, quote_scale) {
+            avatar_width = avatar_width.saturating_sub(step).max(min_width);
+        }
+        let min_scale = quote_scale / 4.0;
+        while quote_scale > min_scale && overflows(avatar_width, quote_scale) {
+            quote_scale *= 0.9;
+        }
+        (avatar_width, quote_scale)
+    }
+
+    // Join the quote spans into one text, with the style of each char
+    fn quote_styles<'f>(
+        &self,
+        config: &ImgConfig,
+        fonts: &'f FontSet,
+        color: [u8; 4],
+    ) -> (String, Vec<components::CharStyle<'f>>) {
+        if !config.parse_markdown && config.quote_spans.is_empty() {
+            let quote = config.quote.as_deref().unwrap_or_default();
+            return (self.normalized(quote), Vec::new());
+        }
+        let spans = if config.quote_spans.is_empty() {
+            vec![(config.quote.clone().unwrap_or_default(), color)]
+        } else {
+            config.quote_spans.clone()
+        };
+
+        let mut quote = String::new();
+        let mut styles = Vec::new();
+        for (text, color) in &spans {
+            let pieces = if config.parse_markdown {
+                components::parse_markdown(text)
+            } else {
+                vec![(text.as_str(), components::Emphasis::Bold)]
+            };
+            for (piece, emphasis) in pieces {
+                // There is no italic font, so it shares the light font with plain text
+                let font = match emphasis {
+                    components::Emphasis::Bold if config.parse_markdown => fonts.accent(),
+                    components::Emphasis::Bold => fonts.quote(),
+                    components::Emphasis::Plain | components::Emphasis::Italic => &fonts.light,
+                };
+                let style = components::CharStyle {
+                    color: Rgba(*color),
+                    font,
+                };
+                let piece = self.normalized(piece);
+                quote.push_str(&piece);
+                styles.extend(piece.chars().map(|_| style));
+            }
+        }
+        (quote, styles)
+    }
+
+    fn normalized(&self, text: &str) -> String {
+        if self.normalize_text {
+            components::normalize_text(text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn load_avatar(
+        &self,
+        data: &SpooledData,
+        config: &ImgConfig,
+        fonts: &FontSet,
+        bg_dim: (u32, u32),
+    ) -> Result<RgbaImage> {
+        // With a fixed quote area, the avatar is fit into the rest of the width
+        let region = self.quote_area_ratio.map(|ratio| {
+            let quote_area_width = (bg_dim.0 as f32 * ratio.clamp(0.0, 1.0)).round() as u32;
+            (bg_dim.0.saturating_sub(quote_area_width).max(1), bg_dim.1)
+        });
+        // Without a fixed region, keep at least half of the width for the quote
+        let max_width = region.is_none().then_some(bg_dim.0 / 2);
+        // The letter avatar takes 1/3 of the background width
+        let letter_size = (bg_dim.0 / 3, bg_dim.1);
+        let avatar = match data {
+            SpooledData::Owned(buffer) => {
+                let data = SpooledData::InMem(buffer);
+                return self.load_avatar(&data, config, fonts, bg_dim);
+            }
             SpooledData::InMem(buffer) => {
-                let img_data = image::load_from_memory(buffer)?.into_rgba8();
-                components::Avatar::builder()
-                    .img_data(img_data)
-                    .bg_height(background.height())
-                    .build()
+                let img_data =
+                    components::decode_avatar(buffer).map_err(ErrorKind::AvatarDecode)?;
+                photo_avatar(
+                    img_data,
+                    config,
+                    self.supersample,
+                    bg_dim.1,
+                    region,
+                    max_width,
+                    self.resize_filter,
+                )?
             }
             SpooledData::OnDisk(path) => {
-                let img_data = image::open(path)?.into_rgba8();
+                let img_data = std::fs::read(path)
+                    .map_err(ImageError::IoError)
+                    .and_then(|buffer| components::decode_avatar(&buffer))
+                    .map_err(ErrorKind::AvatarDecode)?;
+                photo_avatar(
+                    img_data,
+                    config,
+                    self.supersample,
+                    bg_dim.1,
+                    region,
+                    max_width,
+                    self.resize_filter,
+                )?
+            }
+            SpooledData::Decoded(img) => photo_avatar(
+                img.to_rgba8(),
+                config,
+                self.supersample,
+                bg_dim.1,
+                region,
+                max_width,
+                self.resize_filter,
+            )?,
+            SpooledData::TgRandom { id, name } => {
+                let img_data = self.letter_avatar(fonts, *id, name, None, letter_size);
                 components::Avatar::builder()
                     .img_data(img_data)
-                    .bg_height(background.height())
+                    .bg_height(bg_dim.1)
+                    .enable_crop(false)
+                    .region(region)
+                    .filter(config.avatar_filter)
                     .build()
             }
-            SpooledData::TgRandom { id, name } => {
-                let letter = name.chars().next().unwrap().to_string();
-                let info = components::TextDrawInfo::builder()
-                    .text(&letter)
-                    .rgba([255, 255, 255, 255])
-                    .scale(300.0)
-                    .font(&self.font.bold)
-                    .build();
-                let img_data = components::TgAvatar::builder()
-                    .id(*id)
-                    .info(info)
-                    .bg_dim(background.dimensions())
-                    .build();
+            SpooledData::Initials { text, color } => {
+                let id = components::stable_hash(text);
+                let img_data = self.letter_avatar(fonts, id, text, *color, letter_size);
                 components::Avatar::builder()
                     .img_data(img_data)
-                    .bg_height(background.height())
+                    .bg_height(bg_dim.1)
                     .enable_crop(false)
+                    .region(region)
+                    .filter(config.avatar_filter)
                     .build()
             }
         };
-        imageops::overlay(&mut background, &avatar, 0, 0);
+        Ok(avatar)
+    }
 
-        // Step 2: Overlay black gradient to avatar
-        let gradient = components::Transition::builder()
-            .avatar_width(avatar.width())
-            .bg_height(background.height())
-            .build();
-        let offset = (avatar.width() - gradient.width()) as i64;
-        imageops::overlay(&mut background, &gradient, offset, 0);
+    // Width of the area left to the quote column. With the blur fill, the avatar region takes at
+    // least 1/3 of the background.
+    fn avatar_region(&self, avatar_width: u32, bg_width: u32) -> u32 {
+        if self.background_fill == BackgroundFill::BlurAvatar && avatar_width > 0 {
+            std::cmp::max(avatar_width, bg_width / 3)
+        } else {
+            avatar_width
+        }
+    }
 
-        // Step 3: Overlay quotes to background
-        let quote_info = components::TextDrawInfo::builder()
-            .text(&config.quote)
+    // Draw the avatar, then the fill, the gradient and the border of the `region_width` wide
+    // avatar region.
+    fn draw_avatar(&self, background: &mut RgbaImage, avatar: &RgbaImage, region_width: u32) {
+        // Fill the area that the avatar doesn't cover if required
+        if self.background_fill == BackgroundFill::BlurAvatar && avatar.width() < region_width {
+            let fill = components::BlurFill::builder()
+                .img_data(avatar)
+                .region((region_width, background.height()))
+                .build();
+            imageops::overlay(background, &fill, 0, 0);
+        }
+        imageops::overlay(background, avatar, 0, 0);
+        self.step("avatar", background);
+
+        if self.gradient {
+            // Overlay gradient of the background color to avatar
+            let [r, g, b, _] = self.background_color;
+            // Fade over 1/3 of the region, or the given width which can't exceed the region
+            let gradient_width = match (self.gradient_start_ratio, self.gradient_width_px) {
+                (Some(ratio), _) => {
+                    let start = (region_width as f32 * ratio.clamp(0.0, 1.0)).round() as u32;
+                    region_width - start.min(region_width)
+                }
+                (None, Some(w)) => std::cmp::min(w * self.supersample, region_width),
+                (None, None) => region_width / 3,
+            };
+            let gradient = components::Transition::builder()
+                .width(gradient_width)
+                .height(background.height())
+                .starting_color(Rgba([r, g, b, 0]))
+                .ending_color(Rgba([r, g, b, self.gradient_max_alpha]))
+                .build();
+            let offset = (region_width - gradient.width()) as i64;
+            imageops::overlay(background, &gradient, offset, 0);
+            if self.dither {
+                components::dither(background, offset as u32, gradient.width());
+            }
+            self.step("gradient", background);
+        }
+
+        // Draw the border between avatar and quotes
+        if let Some(border) = self.scaled_border() {
+            let x = region_width.saturating_sub(border.width / 2) as i32;
+            let rect = imageproc::rect::Rect::at(x, 0).of_size(border.width, background.height());
+            imageproc::drawing::draw_filled_rect_mut(background, rect, border.color);
+        }
+    }
+
+    #[cfg(feature = "debug-steps")]
+    fn step(&self, label: &str, image: &RgbaImage) {
+        if let Some(hook) = &self.step_hook {
+            hook(label, image);
+        }
+    }
+
+    #[cfg(not(feature = "debug-steps"))]
+    fn step(&self, _label: &str, _image: &RgbaImage) {}
+
+    fn scaled_border(&self) -> Option<BorderConfig> {
+        self.avatar_border.map(|border| BorderConfig {
+            width: border.width * self.supersample,
+            ..border
+        })
+    }
+
+    // Decode the username icon, it is resized by the quotes component
+    fn load_icon(&self, data: &SpooledData, fonts: &FontSet) -> Result<RgbaImage> {
+        // Letter icons are drawn as a square of the username size, so the circle fills the icon
+        let side = (self.font_scale * self.supersample as f32 / 1.5)
+            .ceil()
+            .max(1.0) as u32;
+        match data {
+            SpooledData::Owned(buffer) => self.load_icon(&SpooledData::InMem(buffer), fonts),
+            SpooledData::InMem(buffer) => {
+                components::decode_avatar(buffer).map_err(ErrorKind::IconDecode)
+            }
+            SpooledData::OnDisk(path) => std::fs::read(path)
+                .map_err(ImageError::IoError)
+                .and_then(|buffer| components::decode_avatar(&buffer))
+                .map_err(ErrorKind::IconDecode),
+            SpooledData::Decoded(img) => Ok(img.to_rgba8()),
+            SpooledData::TgRandom { id, name } => {
+                Ok(self.letter_avatar(fonts, *id, name, None, (side, side)))
+            }
+            SpooledData::Initials { text, color } => {
+                let id = components::stable_hash(text);
+                Ok(self.letter_avatar(fonts, id, text, *color, (side, side)))
+            }
+        }
+    }
+
+    fn letter_avatar(
+        &self,
+        fonts: &FontSet,
+        id: u64,
+        text: &str,
+        color: Option<[u8; 4]>,
+        size: (u32, u32),
+    ) -> RgbaImage {
+        let letter = components::initials(text, &fonts.bold, self.letter_avatar_initials);
+        let info = components::TextDrawInfo::builder()
+            .text(&letter)
             .rgba([255, 255, 255, 255])
-            .scale(self.font_scale)
-            .font(&self.font.bold)
-            .build();
-        let user_info = components::TextDrawInfo::builder()
-            .text(&config.username)
-            .rgba([147, 147, 147, 255])
-            .scale(self.font_scale / 1.5)
-            .font(&self.font.light)
-            .build();
-        let quotes = components::Quotes::builder()
-            .avatar_width(avatar.width())
-            .bg_dim(background.dimensions())
-            .quote_info(quote_info)
-            .user_info(user_info)
+            .scale(300.0 * self.supersample as f32)
+            .font(&fonts.bold)
             .build();
-        let offset = avatar.width() as i64;
-        imageops::overlay(&mut background, &quotes, offset, 0);
+        components::TgAvatar::builder()
+            .id(id)
+            .info(info)
+            .size(size)
+            .border(self.scaled_border())
+            .palette(&self.letter_avatar_colors)
+            .color(color)
+            .circle_padding_ratio(self.letter_avatar_padding_ratio)
+            .letter_ratio(self.letter_avatar_letter_ratio)
+            .build()
+    }
+
+    /// Generate an animated GIF which shows each of the quote images for one frame.
+    #[cfg(feature = "gif")]
+    pub fn make_gif(&self, configs: &[ImgConfig], frame_delay_ms: u16) -> Result<Vec<u8>> {
+        use image::codecs::gif::{GifEncoder, Repeat};
+        use image::{Delay, Frame};
+
+        let mut buffer = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut buffer);
+            encoder.set_repeat(Repeat::Infinite)?;
+            for config in configs {
+                let image = self.make_raw_image(config)?;
+                let delay = Delay::from_numer_denom_ms(frame_delay_ms as u32, 1);
+                encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))?;
+            }
+        }
+        Ok(buffer)
+    }
 
-        let mut buffer = Cursor::new(Vec::new());
-        background.write_to(&mut buffer, ImageFormat::Jpeg)?;
-        Ok(buffer.into_inner())
+    /// Render all the configs in parallel, the results are in the same order as the configs.
+    #[cfg(feature = "rayon")]
+    pub fn make_images(&self, configs: &[ImgConfig]) -> Vec<Result<Vec<u8>>> {
+        use rayon::prelude::*;
+
+        configs
+            .par_iter()
+            .map(|config| self.make_image(config))
+            .collect()
+    }
+}
+
+#[cfg(feature = "async")]
+impl QuoteProducer<'static> {
+    /// Run [`QuoteProducer::make_image`] on the tokio blocking thread pool, so the CPU heavy
+    /// rendering doesn't stall other tasks on the async executor. The work is moved to another
+    /// thread, so the producer and the config can't borrow any local data. Share the producer
+    /// with an `Arc`, and load the fonts with `include_bytes!` or leak them.
+    pub async fn make_image_async(
+        self: std::sync::Arc<Self>,
+        config: ImgConfig<'static>,
+    ) -> Result<Vec<u8>> {
+        let task = tokio::task::spawn_blocking(move || self.make_image(&config));
+        match task.await {
+            Ok(result) => result,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(_) => Err(ErrorKind::Cancelled),
+        }
     }
 }
 
@@ -213,8 +1544,33 @@ impl<'font> QuoteProducer<'font> {
 pub enum ErrorKind {
     #[error("internal image library error: {0}")]
     ImgErr(#[from] ImageError),
+    #[error("fail to decode avatar: {0}")]
+    AvatarDecode(ImageError),
+    #[error("fail to decode username icon: {0}")]
+    IconDecode(ImageError),
+    #[error("invalid output dimensions {width}x{height}: {reason}")]
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+        reason: &'static str,
+    },
+    #[error("crop rect {rect:?} is outside of the {}x{} avatar", avatar.0, avatar.1)]
+    InvalidCropRect {
+        rect: (u32, u32, u32, u32),
+        avatar: (u32, u32),
+    },
     #[error("fail to read font: {0}")]
     FontErr(#[from] std::io::Error),
+    #[error("fail to parse font: {0}")]
+    InvalidFont(&'static str),
+    #[error("output format {0:?} can't keep the transparent background")]
+    TransparencyUnsupported(ImageFormat),
+    #[error("required field `{0}` is missing")]
+    MissingField(&'static str),
+    #[error("output is {size} bytes at the lowest quality, over the limit of {limit} bytes")]
+    OutputTooLarge { size: usize, limit: usize },
+    #[error("the render task is cancelled")]
+    Cancelled,
 }
 
 type Result<T, E = ErrorKind> = core::result::Result<T, E>;
@@ -226,7 +1582,7 @@ fn test_create_background_image() {
     let bold_font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap();
     let light_font = include_bytes!("/usr/share/fonts/noto-cjk/NotoSansCJK-Light.ttc");
     let builder = QuoteProducer::builder()
-        .font(&bold_font, light_font)
+        .font(FontSet::new(&bold_font, light_font).unwrap())
         .build();
 
     let config = ImgConfig::builder()
@@ -251,3 +1607,2224 @@ fn test_create_background_image() {
     let buffer = builder.make_image(&config).unwrap();
     std::fs::write("./assets/test-tg.jpg", buffer).unwrap();
 }
+
+#[test]
+fn test_blur_avatar_fill() {
+    // A wide and short canvas makes the square avatar narrower than the avatar region
+    let producer = QuoteProducer {
+        font: testing::test_font_pair(),
+        font_scale: 40.0,
+        background_fill: BackgroundFill::BlurAvatar,
+        ..testing::test_producer(1200, 200)
+    };
+    let config = ImgConfig::builder()
+        .username("@V5电竞俱乐部中单选手 Otto")
+        .avatar("./assets/avatar.png")
+        .quote("大家好")
+        .build();
+
+    let buffer = producer.make_image(&config).unwrap();
+    let image = image::load_from_memory(&buffer).unwrap().into_rgba8();
+    // avatar width is 150px, and the avatar region is 400px wide. Its last third fades into the
+    // background.
+    for x in [170, 220, 260] {
+        let pixel = image.get_pixel(x, 100);
+        assert!(pixel.0[..3].iter().any(|c| *c > 16), "pixel at {x} is black");
+    }
+    // The quote column starts after the fill, the white quote is drawn right to it
+    assert_eq!(producer.avatar_region_width(&config).unwrap(), 400);
+    let quote_ink: Vec<u32> = image
+        .enumerate_pixels()
+        .filter(|(_, y, p)| *y < 100 && p.0[..3].iter().all(|c| *c > 240))
+        .map(|(x, _, _)| x)
+        .collect();
+    assert!(!quote_ink.is_empty());
+    assert!(
+        quote_ink.iter().all(|x| *x >= 400),
+        "{:?}",
+        quote_ink.iter().min()
+    );
+}
+
+#[test]
+fn test_avatar_border() {
+    let producer = QuoteProducer {
+        font: testing::test_font_pair(),
+        font_scale: 40.0,
+        avatar_border: Some(BorderConfig {
+            width: 8,
+            color: image::Rgba([255, 0, 0, 255]),
+        }),
+        ..testing::test_producer(1200, 400)
+    };
+    let config = ImgConfig::builder()
+        .username("@V5电竞俱乐部中单选手 Otto")
+        .avatar("./assets/avatar.png")
+        .quote("大家好")
+        .build();
+
+    let buffer = producer.make_image(&config).unwrap();
+    let image = image::load_from_memory(&buffer).unwrap().into_rgba8();
+    // avatar is 300px wide, so the boundary sits at column 300
+    for y in (0..400).step_by(50) {
+        let [r, g, b, _] = image.get_pixel(300, y).0;
+        assert!(r > 200 && g < 60 && b < 60, "pixel at y={y} is not red");
+    }
+}
+
+#[cfg(feature = "gif")]
+#[test]
+fn test_make_gif() {
+    use image::AnimationDecoder;
+
+    let producer = QuoteProducer {
+        font: testing::test_font_pair(),
+        font_scale: 20.0,
+        ..testing::test_producer(320, 180)
+    };
+    let configs = ["大家好", "今天来点", "大家想看的东西"].map(|quote| {
+        ImgConfig::builder()
+            .username("@V5电竞俱乐部中单选手 Otto")
+            .avatar("./assets/avatar.png")
+            .quote(quote)
+            .build()
+    });
+
+    let buffer = producer.make_gif(&configs, 500).unwrap();
+    let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(buffer)).unwrap();
+    assert_eq!(decoder.into_frames().count(), configs.len());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_make_images() {
+    let producer = QuoteProducer {
+        font_scale: 20.0,
+        output_format: ImageFormat::Png,
+        ..testing::test_producer(320, 180)
+    };
+    let configs = ["大家好", "今天来点", "大家想看的东西", ""].map(|quote| {
+        ImgConfig::builder()
+            .username("@V5电竞俱乐部中单选手 Otto")
+            .avatar("./assets/avatar.png")
+            .quote(quote)
+            .build()
+    });
+
+    let parallel = producer.make_images(&configs);
+    assert_eq!(parallel.len(), configs.len());
+    for (result, config) in parallel.into_iter().zip(&configs) {
+        assert_eq!(result.unwrap(), producer.make_image(config).unwrap());
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+#[tokio::test]
+async fn test_make_image_async() {
+    let producer = std::sync::Arc::new(QuoteProducer {
+        font_scale: 20.0,
+        output_format: ImageFormat::Png,
+        ..testing::test_producer(320, 180)
+    });
+    let config = || {
+        ImgConfig::builder()
+            .username("@ksyxmeow")
+            .avatar("./assets/avatar.png")
+            .quote("大家好")
+            .build()
+    };
+
+    let sync_output = producer.make_image(&config()).unwrap();
+    let async_output = producer.clone().make_image_async(config()).await.unwrap();
+    assert_eq!(sync_output, async_output);
+}
+
+#[test]
+fn test_animated_gif_avatar() {
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame, Rgba};
+
+    let mut gif = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut gif);
+        for color in [[255, 0, 0, 255], [0, 0, 255, 255]] {
+            let frame = RgbaImage::from_pixel(64, 64, Rgba(color));
+            let delay = Delay::from_numer_denom_ms(100, 1);
+            encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay)).unwrap();
+        }
+    }
+
+    let producer = QuoteProducer {
+        font: testing::test_font_pair(),
+        font_scale: 40.0,
+        ..testing::test_producer(640, 360)
+    };
+    let config = ImgConfig::builder()
+        .username("@V5电竞俱乐部中单选手 Otto")
+        .avatar(gif.as_slice())
+        .quote("大家好")
+        .build();
+
+    let image = producer.make_raw_image(&config).unwrap();
+    assert_eq!(image.dimensions(), (640, 360));
+    // The first frame is red
+    let [r, g, b, _] = image.get_pixel(10, 10).0;
+    assert!(r > 200 && g < 50 && b < 50);
+}
+
+#[test]
+fn test_invalid_avatar() {
+    let producer = QuoteProducer {
+        font: testing::test_font_pair(),
+        ..testing::test_producer(1920, 1080)
+    };
+    let config = ImgConfig::builder()
+        .username("@V5电竞俱乐部中单选手 Otto")
+        .avatar(b"definitely not an image".as_slice())
+        .quote("大家好")
+        .build();
+
+    let result = producer.make_image(&config);
+    assert!(matches!(result, Err(ErrorKind::AvatarDecode(_))));
+}
+
+#[test]
+fn test_avatar_crop_rect() {
+    // A red square in the middle of a blue image
+    let avatar = RgbaImage::from_fn(400, 400, |x, y| {
+        if (100..200).contains(&x) && (150..250).contains(&y) {
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([0, 0, 255, 255])
+        }
+    });
+    let mut buffer = Cursor::new(Vec::new());
+    avatar.write_to(&mut buffer, ImageFormat::Png).unwrap();
+    let buffer = buffer.into_inner();
+    let producer = QuoteProducer {
+        font_scale: 30.0,
+        output_format: ImageFormat::Png,
+        ..testing::test_producer(900, 300)
+    };
+
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("")
+        .avatar(buffer.as_slice())
+        .avatar_crop_rect((100, 150, 100, 100))
+        .build();
+    let image = producer.make_raw_image(&config).unwrap();
+    // The red square is scaled to cover the whole 300px high avatar
+    for (x, y) in [(10, 10), (150, 150), (10, 290)] {
+        let [r, _, b, _] = image.get_pixel(x, y).0;
+        assert!(r > 200 && b < 50, "pixel at ({x}, {y}) is not red");
+    }
+
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("")
+        .avatar(buffer.as_slice())
+        .avatar_crop_rect((350, 0, 100, 100))
+        .build();
+    let result = producer.make_raw_image(&config);
+    assert!(matches!(
+        result,
+        Err(ErrorKind::InvalidCropRect {
+            rect: (350, 0, 100, 100),
+            avatar: (400, 400),
+        })
+    ));
+
+    // A zero sized rect inside the avatar is rejected too
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("")
+        .avatar(buffer.as_slice())
+        .avatar_crop_rect((0, 0, 0, 100))
+        .build();
+    let result = producer.make_raw_image(&config);
+    assert!(matches!(result, Err(ErrorKind::InvalidCropRect { .. })));
+}
+
+#[test]
+fn test_invalid_dimensions() {
+    let config = ImgConfig::builder()
+        .username("@V5电竞俱乐部中单选手 Otto")
+        .avatar("./assets/avatar.png")
+        .quote("大家好")
+        .build();
+
+    let producer = QuoteProducer {
+        font: testing::test_font_pair(),
+        ..testing::test_producer(0, 1080)
+    };
+    let result = producer.make_image(&config);
+    assert!(matches!(result, Err(ErrorKind::InvalidDimensions { .. })));
+
+    // A very wide avatar is cropped to half of the canvas after being scaled to the canvas height
+    let mut wide_avatar = Cursor::new(Vec::new());
+    RgbaImage::new(4000, 100)
+        .write_to(&mut wide_avatar, ImageFormat::Png)
+        .unwrap();
+    let wide_avatar = wide_avatar.into_inner();
+    let config = ImgConfig::builder()
+        .username("@V5电竞俱乐部中单选手 Otto")
+        .avatar(wide_avatar.as_slice())
+        .quote("大家好")
+        .build();
+    let producer = QuoteProducer {
+        font: testing::test_font_pair(),
+        ..testing::test_producer(640, 360)
+    };
+    assert_eq!(producer.avatar_region_width(&config).unwrap(), 320);
+    assert!(producer.make_image(&config).is_ok());
+}
+
+#[test]
+fn test_dither_gradient() {
+    let data = SpooledData::TgRandom {
+        id: 13,
+        name: "ksyx".to_string(),
+    };
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .avatar(&data)
+        .quote("教授可爱喵喵喵")
+        .build();
+    // The letter avatar is 200px wide, and the area above the circle is flat black
+    let variance = |dither: bool| {
+        let producer = QuoteProducer {
+            font: testing::test_font_pair(),
+            font_scale: 40.0,
+            dither,
+            ..testing::test_producer(600, 600)
+        };
+        let image = producer.make_raw_image(&config).unwrap();
+        let band: Vec<f32> = (0..50)
+            .map(|y| image.get_pixel(190, y).0[0] as f32)
+            .collect();
+        let mean = band.iter().sum::<f32>() / band.len() as f32;
+        band.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / band.len() as f32
+    };
+
+    assert_eq!(variance(false), 0.0);
+    assert!(variance(true) > 0.0);
+}
+
+#[test]
+fn test_no_avatar() {
+    let producer = QuoteProducer {
+        font: testing::test_font_pair(),
+        font_scale: 60.0,
+        ..testing::test_producer(800, 400)
+    };
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+
+    let image = producer.make_raw_image(&config).unwrap();
+    // The username should be horizontally centered on the whole canvas
+    let xs = image
+        .enumerate_pixels()
+        .filter(|(_, y, p)| *y >= 300 && p.0[0] > 64)
+        .map(|(x, _, _)| x as i32);
+    let (left, right) = xs.fold((i32::MAX, 0), |(l, r), x| (l.min(x), r.max(x)));
+    let center = (left + right) / 2;
+    assert!((center - 400).abs() <= 4, "username centered at {center}");
+}
+
+#[test]
+fn test_font_collection_index() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc").unwrap();
+    let config = ImgConfig::builder()
+        .username("@V5电竞俱乐部中单选手 Otto")
+        .quote("直骨 Aa")
+        .build();
+    let render = |index: u32| {
+        let producer = QuoteProducer {
+            font: FontSet::with_indices(&font, index, &font, 0).unwrap(),
+            font_scale: 60.0,
+            ..testing::test_producer(640, 360)
+        };
+        producer.make_raw_image(&config).unwrap()
+    };
+
+    assert_ne!(render(0), render(1));
+}
+
+#[test]
+fn test_single_font() {
+    let producer = QuoteProducer {
+        font_scale: 60.0,
+        ..testing::test_producer(640, 360)
+    };
+    let config = ImgConfig::builder()
+        .username("@V5电竞俱乐部中单选手 Otto")
+        .avatar("./assets/avatar.png")
+        .quote("大家好")
+        .build();
+    producer.make_image(&config).unwrap();
+
+    // The face index is used by both roles
+    let collection = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc").unwrap();
+    let render = |fonts: FontSet| {
+        let producer = QuoteProducer {
+            font: fonts,
+            font_scale: 60.0,
+            ..testing::test_producer(640, 360)
+        };
+        producer.make_raw_image(&config).unwrap()
+    };
+    let single = render(FontSet::single(&collection, 1).unwrap());
+    assert_eq!(
+        single,
+        render(FontSet::with_indices(&collection, 1, &collection, 1).unwrap())
+    );
+    assert_ne!(
+        single,
+        render(FontSet::with_indices(&collection, 1, &collection, 0).unwrap())
+    );
+}
+
+#[test]
+fn test_synthetic_weights() {
+    let font = testing::test_font();
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let render = |fonts: FontSet, synthetic_weights: bool| {
+        let producer = QuoteProducer {
+            font: fonts,
+            font_scale: 90.0,
+            synthetic_weights,
+            ..testing::test_producer(800, 400)
+        };
+        let image = producer.make_raw_image(&config).unwrap();
+        let quote_ink = image
+            .enumerate_pixels()
+            .filter(|(_, y, p)| *y < 250 && p.0[0] > 127)
+            .count();
+        let username_brightness = image
+            .enumerate_pixels()
+            .filter(|(_, y, _)| *y >= 250)
+            .map(|(_, _, p)| p.0[0])
+            .max()
+            .unwrap();
+        (quote_ink, username_brightness)
+    };
+
+    let (regular_ink, regular_brightness) = render(FontSet::single(font, 0).unwrap(), false);
+    let (bold_ink, light_brightness) = render(FontSet::single(font, 0).unwrap(), true);
+    assert!(bold_ink > regular_ink);
+    assert!(light_brightness < regular_brightness);
+    // The same data for both roles is the same face too
+    let same_data = FontSet::new(font, font).unwrap();
+    assert_eq!(render(same_data, true), (bold_ink, light_brightness));
+
+    // Real bold and light faces are kept as they are
+    let light = testing::test_light_font();
+    let two_faces =
+        |synthetic_weights| render(FontSet::new(font, light).unwrap(), synthetic_weights);
+    assert_eq!(two_faces(true), two_faces(false));
+}
+
+#[test]
+fn test_quote_spans() {
+    let producer = QuoteProducer {
+        font_scale: 80.0,
+        ..testing::test_producer(800, 400)
+    };
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote_spans([("Hello ", [255, 0, 0, 255]), ("World", [0, 0, 255, 255])])
+        .build();
+
+    let image = producer.make_raw_image(&config).unwrap();
+    let quote_pixels = || image.enumerate_pixels().filter(|(_, y, _)| *y < 250);
+    let red = quote_pixels().filter(|(_, _, p)| p.0[0] > 200 && p.0[2] < 50);
+    let blue = quote_pixels().filter(|(_, _, p)| p.0[2] > 200 && p.0[0] < 50);
+    let (red_right, blue_left) = (
+        red.map(|(x, _, _)| x).max().unwrap(),
+        blue.map(|(x, _, _)| x).min().unwrap(),
+    );
+    // Both colors are on the same line, red goes first
+    assert!(red_right < blue_left);
+}
+
+#[test]
+fn test_markdown_quote() {
+    let producer = QuoteProducer {
+        font: testing::test_font_pair(),
+        font_scale: 80.0,
+        ..testing::test_producer(800, 400)
+    };
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("hello *world*")
+        .parse_markdown(true)
+        .build();
+    let image = producer.make_raw_image(&config).unwrap();
+
+    // Light "hello " goes on the left half and bold "world" goes on the right half, and the bold
+    // font puts a lot more ink on the canvas.
+    let ink = |range: std::ops::Range<u32>| {
+        image
+            .enumerate_pixels()
+            .filter(|(x, y, p)| range.contains(x) && *y < 250 && p.0[0] > 127)
+            .count()
+    };
+    let (hello_ink, world_ink) = (ink(0..400), ink(400..800));
+    assert!(world_ink > hello_ink * 2, "{hello_ink} vs {world_ink}");
+}
+
+#[test]
+fn test_letter_spacing() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let quote_width = |letter_spacing: f32| {
+        let producer = QuoteProducer {
+            font_scale: 80.0,
+            letter_spacing,
+            ..testing::test_producer(800, 400)
+        };
+        let image = producer.make_raw_image(&config).unwrap();
+        let xs = image
+            .enumerate_pixels()
+            .filter(|(_, y, p)| *y < 250 && p.0[0] > 127)
+            .map(|(x, _, _)| x);
+        let (left, right) = xs.fold((u32::MAX, 0), |(l, r), x| (l.min(x), r.max(x)));
+        right - left
+    };
+
+    // 4 gaps between 5 chars
+    let (normal, spaced) = (quote_width(0.0), quote_width(10.0));
+    assert!((spaced as i32 - normal as i32 - 40).abs() <= 2);
+}
+
+#[test]
+fn test_custom_composition() {
+    let font = testing::test_font();
+    let font = Font::try_from_bytes(font).unwrap();
+
+    let mut background: RgbaImage = Background::builder()
+        .output_dimension((800, 400))
+        .color(Rgba([255, 255, 255, 255]))
+        .build();
+    let avatar: RgbaImage = Avatar::builder()
+        .img_data(image::open("./assets/avatar.png").unwrap().into_rgba8())
+        .bg_height(background.height())
+        .build();
+    // Put the gradient under the avatar, so it only shows up outside the avatar
+    let gradient: RgbaImage = Transition::builder()
+        .width(background.width() / 3)
+        .height(background.height())
+        .build();
+    imageops::overlay(&mut background, &gradient, 0, 0);
+    imageops::overlay(&mut background, &avatar, 0, 0);
+    let quotes: RgbaImage = Quotes::builder()
+        .avatar_width(avatar.width())
+        .bg_dim(background.dimensions())
+        .quote_info(
+            TextDrawInfo::builder()
+                .text("Hello")
+                .rgba([0, 0, 0, 255])
+                .scale(60.0)
+                .font(&font)
+                .build(),
+        )
+        .user_info(
+            TextDrawInfo::builder()
+                .text("@ksyxmeow")
+                .rgba([64, 64, 64, 255])
+                .scale(40.0)
+                .font(&font)
+                .build(),
+        )
+        .build();
+    imageops::overlay(&mut background, &quotes, avatar.width() as i64, 0);
+
+    assert_eq!(background.dimensions(), (800, 400));
+    assert_eq!(background.get_pixel(799, 0).0[..3], [255, 255, 255]);
+    // The opaque avatar covers the gradient under it
+    let opaque = avatar.enumerate_pixels().filter(|(_, _, p)| p.0[3] == 255);
+    assert!(opaque.clone().count() > 0);
+    for (x, y, pixel) in opaque {
+        assert_eq!(background.get_pixel(x, y), pixel, "({x}, {y})");
+    }
+    // The quote is drawn above the middle line and the username below it, right to the avatar
+    let inked = |rows: std::ops::Range<u32>, color: [u8; 3]| {
+        rows.flat_map(|y| (avatar.width()..800).map(move |x| (x, y)))
+            .filter(|&(x, y)| background.get_pixel(x, y).0[..3] == color)
+            .count()
+    };
+    assert!(inked(0..200, [0, 0, 0]) > 100);
+    assert!(inked(200..400, [64, 64, 64]) > 50);
+    assert_eq!(inked(0..400, [0, 0, 0]), inked(0..200, [0, 0, 0]));
+}
+
+#[test]
+fn test_transparent_background() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+
+    let producer = QuoteProducer {
+        font_scale: 60.0,
+        transparent_background: true,
+        output_format: ImageFormat::Png,
+        ..testing::test_producer(800, 400)
+    };
+    let buffer = producer.make_image(&config).unwrap();
+    let image = image::load_from_memory(&buffer).unwrap().into_rgba8();
+    assert_eq!(image.get_pixel(0, 0).0[3], 0);
+    assert_eq!(image.get_pixel(799, 399).0[3], 0);
+    assert!(image.pixels().any(|p| p.0[3] == 255));
+
+    let producer = QuoteProducer {
+        transparent_background: true,
+        ..testing::test_producer(1920, 1080)
+    };
+    let result = producer.make_image(&config);
+    assert!(matches!(result, Err(ErrorKind::TransparencyUnsupported(_))));
+}
+
+#[test]
+fn test_tg_avatar_from_name() {
+    let id = |data: SpooledData| match data {
+        SpooledData::TgRandom { id, .. } => id,
+        _ => unreachable!(),
+    };
+    assert_eq!(
+        id(SpooledData::tg_from_name("ksyx")),
+        id(SpooledData::tg_from_name("ksyx"))
+    );
+    // The hash must not change between runs or versions, or users would see their color change
+    assert_eq!(id(SpooledData::tg_from_name("ksyx")), 12590123746390343578);
+}
+
+#[test]
+fn test_corner_radius() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .avatar("./assets/avatar.png")
+        .quote("Hello")
+        .build();
+
+    let producer = QuoteProducer {
+        font_scale: 60.0,
+        corner_radius: Some(40),
+        output_format: ImageFormat::Png,
+        ..testing::test_producer(800, 400)
+    };
+    let buffer = producer.make_image(&config).unwrap();
+    let image = image::load_from_memory(&buffer).unwrap().into_rgba8();
+    for (x, y) in [(0, 0), (799, 0), (0, 399), (799, 399)] {
+        assert_eq!(image.get_pixel(x, y).0[3], 0);
+    }
+    assert_eq!(image.get_pixel(400, 200).0[3], 255);
+
+    let producer = QuoteProducer {
+        corner_radius: Some(40),
+        ..testing::test_producer(1920, 1080)
+    };
+    let result = producer.make_image(&config);
+    assert!(matches!(result, Err(ErrorKind::TransparencyUnsupported(_))));
+}
+
+#[test]
+fn test_drop_shadow() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let shadow = DropShadow {
+        blur: 6.0,
+        offset: (0, 8),
+        color: Rgba([0, 0, 0, 160]),
+        spread: 0,
+    };
+
+    let producer = QuoteProducer {
+        font_scale: 40.0,
+        drop_shadow: Some(shadow),
+        output_format: ImageFormat::Png,
+        ..testing::test_producer(400, 200)
+    };
+    let buffer = producer.make_image(&config).unwrap();
+    let image = image::load_from_memory(&buffer).unwrap().into_rgba8();
+    let margin = shadow.margin();
+    assert_eq!(image.dimensions(), (400 + margin * 2, 200 + margin * 2));
+    // Right below the card
+    assert!(image.get_pixel(margin + 200, margin + 200 + 2).0[3] > 0);
+    assert_eq!(image.get_pixel(0, 0).0[3], 0);
+}
+
+#[test]
+fn test_line_cache() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("The same popular quote over and over again")
+        .build();
+    let producer = QuoteProducer {
+        font_scale: 40.0,
+        line_cache: components::LineCache::new(8),
+        ..testing::test_producer(800, 400)
+    };
+
+    let first = producer.make_raw_image(&config).unwrap();
+    assert_eq!(producer.line_cache.measured(), 1);
+    let second = producer.make_raw_image(&config).unwrap();
+    assert_eq!(producer.line_cache.measured(), 1);
+    assert!(first == second);
+}
+
+#[test]
+fn test_glyph_cache_option() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("大家好，The quick brown fox")
+        .build();
+    let producer = |capacity| QuoteProducer {
+        font_scale: 40.0,
+        glyph_cache: components::GlyphCache::new(capacity),
+        ..testing::test_producer(800, 400)
+    };
+
+    let (cached, disabled) = (producer(8192), producer(0));
+    let image = cached.make_raw_image(&config).unwrap();
+    assert!(!cached.glyph_cache.is_empty());
+    assert!(image == disabled.make_raw_image(&config).unwrap());
+    assert!(disabled.glyph_cache.is_empty());
+}
+
+#[test]
+fn test_nearest_resize_filter() {
+    // Hard-edged 4x4 checkerboard of pure black and white
+    let avatar = RgbaImage::from_fn(4, 4, |x, y| {
+        if (x + y) % 2 == 0 {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    });
+    let mut buffer = Cursor::new(Vec::new());
+    avatar.write_to(&mut buffer, ImageFormat::Png).unwrap();
+    let buffer = buffer.into_inner();
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("")
+        .avatar(buffer.as_slice())
+        .build();
+    let avatar_pixels = |filter| {
+        let producer = QuoteProducer {
+            font_scale: 30.0,
+            resize_filter: filter,
+            ..testing::test_producer(900, 400)
+        };
+        let image = producer.make_raw_image(&config).unwrap();
+        // Only look at the avatar, left to the gradient
+        imageops::crop_imm(&image, 0, 0, 200, 400).to_image()
+    };
+
+    let is_hard = |p: &Rgba<u8>| p.0[..3].iter().all(|c| *c == 0 || *c == 255);
+    assert!(avatar_pixels(FilterType::Nearest).pixels().all(is_hard));
+    assert!(!avatar_pixels(FilterType::CatmullRom).pixels().all(is_hard));
+}
+
+#[test]
+fn test_embed_metadata() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("大家好")
+        .build();
+
+    let producer = QuoteProducer {
+        font_scale: 20.0,
+        output_format: ImageFormat::Png,
+        embed_metadata: true,
+        ..testing::test_producer(320, 180)
+    };
+    let buffer = producer.make_image(&config).unwrap();
+    let reader = png::Decoder::new(buffer.as_slice()).read_info().unwrap();
+    let texts: Vec<_> = reader
+        .info()
+        .utf8_text
+        .iter()
+        .map(|chunk| (chunk.keyword.as_str(), chunk.get_text().unwrap()))
+        .collect();
+    assert!(texts.contains(&("Description", "大家好".to_string())));
+    assert!(texts.contains(&("Author", "@ksyxmeow".to_string())));
+
+    let producer = QuoteProducer {
+        font_scale: 20.0,
+        embed_metadata: true,
+        ..testing::test_producer(320, 180)
+    };
+    let buffer = producer.make_image(&config).unwrap();
+    // Find the COM segment and check it is still a valid JPEG
+    let start = buffer.windows(2).position(|w| w == [0xFF, 0xFE]).unwrap();
+    let length = u16::from_be_bytes([buffer[start + 2], buffer[start + 3]]) as usize;
+    let comment = std::str::from_utf8(&buffer[start + 4..start + 2 + length]).unwrap();
+    assert_eq!(comment, "大家好\n-- @ksyxmeow");
+    image::load_from_memory(&buffer).unwrap();
+
+    // The JFIF segment with the density stays first, the comment follows it
+    let producer = QuoteProducer {
+        font_scale: 20.0,
+        dpi: Some(300),
+        embed_metadata: true,
+        ..testing::test_producer(320, 180)
+    };
+    let buffer = producer.make_image(&config).unwrap();
+    assert_eq!(buffer[2..4], [0xFF, 0xE0]);
+    assert_eq!(&buffer[6..11], b"JFIF\0");
+    let app0_end = 4 + u16::from_be_bytes([buffer[4], buffer[5]]) as usize;
+    assert_eq!(buffer[app0_end..app0_end + 2], [0xFF, 0xFE]);
+    image::load_from_memory(&buffer).unwrap();
+}
+
+#[test]
+fn test_quote_report() {
+    let producer = QuoteProducer {
+        font_scale: 40.0,
+        ..testing::test_producer(600, 300)
+    };
+
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let (_, report) = producer.make_image_with_report(&config).unwrap();
+    assert_eq!(report.line_count, 1);
+    assert!(!report.overflowed);
+
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("A quote that is way too long to fit in this small image. ".repeat(5))
+        .build();
+    let (_, report) = producer.make_image_with_report(&config).unwrap();
+    assert!(report.line_count > 4);
+    assert!(report.overflowed);
+
+    // The quote fits, but the tall username block is moved up over it
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow\n".repeat(6).trim_end())
+        .quote("Hello")
+        .build();
+    let (_, report) = producer.make_image_with_report(&config).unwrap();
+    assert_eq!(report.line_count, 1);
+    assert!(report.overflowed);
+}
+
+#[test]
+fn test_username_position() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    // Rows with the grey username ink, and rows with the white quote ink
+    let rows = |position| {
+        let producer = QuoteProducer {
+            font_scale: 40.0,
+            username_position: position,
+            output_format: ImageFormat::Png,
+            ..testing::test_producer(600, 400)
+        };
+        let image = producer.make_raw_image(&config).unwrap();
+        let rows_of = |color: [u8; 3]| -> Vec<u32> {
+            image
+                .enumerate_pixels()
+                .filter(|(_, _, p)| p.0[..3] == color)
+                .map(|(_, y, _)| y)
+                .collect()
+        };
+        (rows_of([147, 147, 147]), rows_of([255, 255, 255]))
+    };
+
+    let (username, quote) = rows(Vertical::Top);
+    assert!(username.iter().all(|y| *y < 100));
+    assert!(quote.iter().all(|y| *y >= 200));
+
+    let (username, quote) = rows(Vertical::Bottom);
+    assert!(username.iter().all(|y| *y >= 300));
+    assert!(quote.iter().all(|y| *y < 200));
+}
+
+#[test]
+fn test_username_with_handle() {
+    let config = ImgConfig::builder()
+        .username("Otto")
+        .handle("otto_v5")
+        .quote("Hello")
+        .build();
+    let producer = QuoteProducer {
+        font_scale: 40.0,
+        ..testing::test_producer(600, 400)
+    };
+    let image = producer.make_raw_image(&config).unwrap();
+
+    // The attribution area has both the display name and the dimmer handle below it
+    let rows_of = |color: [u8; 3]| {
+        image
+            .enumerate_pixels()
+            .filter(|(_, y, p)| *y >= 300 && p.0[..3] == color)
+            .map(|(_, y, _)| y)
+            .collect::<Vec<_>>()
+    };
+    let name = rows_of([147, 147, 147]);
+    let handle = rows_of([100, 100, 100]);
+    assert!(!name.is_empty() && !handle.is_empty());
+    assert!(name.iter().max() < handle.iter().min());
+}
+
+#[test]
+fn test_username_align() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let username_columns = |align| {
+        let producer = QuoteProducer {
+            font_scale: 40.0,
+            username_align: align,
+            ..testing::test_producer(900, 300)
+        };
+        let image = producer.make_raw_image(&config).unwrap();
+        let columns = image
+            .enumerate_pixels()
+            .filter(|(_, y, p)| *y >= 200 && p.0[..3] == [147, 147, 147])
+            .map(|(x, _, _)| x);
+        columns.fold((u32::MAX, 0), |(l, r), x| (l.min(x), r.max(x)))
+    };
+
+    // The default gap between text and the canvas edge is 30px
+    let (_, right) = username_columns(Horizontal::Right);
+    assert!(
+        (900 - 30 - right as i32).abs() <= 4,
+        "right edge at {right}"
+    );
+    let (left, _) = username_columns(Horizontal::Left);
+    // The ink starts a bit after x because of the left side bearing of "@"
+    assert!((left as i32 - 30).abs() <= 8, "left edge at {left}");
+}
+
+#[test]
+fn test_golden_layout() {
+    // No text in the image, so the reference doesn't depend on the installed fonts
+    let config = ImgConfig::builder()
+        .username("")
+        .quote("")
+        .avatar("./assets/avatar.png")
+        .build();
+
+    let producer = testing::test_producer(480, 270);
+    testing::assert_golden("layout", &producer.make_raw_image(&config).unwrap());
+
+    let producer = QuoteProducer {
+        background_fill: BackgroundFill::BlurAvatar,
+        avatar_border: Some(BorderConfig {
+            width: 4,
+            color: Rgba([255, 255, 255, 255]),
+        }),
+        corner_radius: Some(24),
+        ..testing::test_producer(480, 270)
+    };
+    testing::assert_golden(
+        "layout_decorated",
+        &producer.make_raw_image(&config).unwrap(),
+    );
+}
+
+#[test]
+fn test_max_lines() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("A quote that is way too long to fit in this small image. ".repeat(5))
+        .build();
+    let producer = QuoteProducer {
+        font_scale: 40.0,
+        max_lines: Some(2),
+        ..testing::test_producer(600, 300)
+    };
+
+    let (_, report) = producer.make_image_with_report(&config).unwrap();
+    assert_eq!(report.line_count, 2);
+    assert!(!report.overflowed);
+
+    let dots = QuoteProducer {
+        font_scale: 40.0,
+        max_lines: Some(2),
+        ellipsis: "...".into(),
+        ..testing::test_producer(600, 300)
+    };
+    assert_ne!(
+        dots.make_raw_image(&config).unwrap(),
+        producer.make_raw_image(&config).unwrap()
+    );
+}
+
+#[test]
+fn test_background_color() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .avatar("./assets/avatar.png")
+        .quote("Hello")
+        .build();
+    let producer = QuoteProducer {
+        font_scale: 30.0,
+        background_color: [255, 255, 255, 255],
+        ..testing::test_producer(900, 300)
+    };
+    let image = producer.make_raw_image(&config).unwrap();
+    assert_eq!(image.get_pixel(890, 10).0, [255, 255, 255, 255]);
+    // The gradient fades the avatar into the background color too
+    let avatar_width = 300 - 300 / 4;
+    assert_eq!(
+        image.get_pixel(avatar_width - 1, 150).0,
+        [255, 255, 255, 255]
+    );
+}
+
+#[test]
+fn test_output_filter() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .avatar("./assets/avatar.png")
+        .quote("Hello")
+        .build();
+    let producer = QuoteProducer {
+        font_scale: 30.0,
+        output_filter: Some(OutputFilter::Grayscale),
+        output_format: ImageFormat::Png,
+        ..testing::test_producer(900, 300)
+    };
+    let buffer = producer.make_image(&config).unwrap();
+    let image = image::load_from_memory(&buffer).unwrap().into_rgba8();
+    // The avatar is colorful before the filter
+    let [r, g, b, _] = image.get_pixel(100, 150).0;
+    assert!(r == g && g == b);
+}
+
+#[test]
+fn test_username_font() {
+    let bold_font = testing::test_font();
+    let light_font = testing::test_light_font();
+    let username_font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc").unwrap();
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let render = |producer: QuoteProducer| producer.make_raw_image(&config).unwrap();
+
+    let with_role = render(QuoteProducer {
+        font: FontSet::new(bold_font, light_font)
+            .unwrap()
+            .username_font(Font::try_from_bytes(&username_font).unwrap()),
+        font_scale: 40.0,
+        ..testing::test_producer(600, 300)
+    });
+    // Same as using the third font as the light font, since only the username uses it here
+    let as_light = render(QuoteProducer {
+        font: FontSet::new(bold_font, &username_font).unwrap(),
+        font_scale: 40.0,
+        ..testing::test_producer(600, 300)
+    });
+    let without_role = render(QuoteProducer {
+        font: testing::test_font_pair(),
+        font_scale: 40.0,
+        ..testing::test_producer(600, 300)
+    });
+    assert_eq!(with_role, as_light);
+    assert_ne!(with_role, without_role);
+
+    // Any face of a font collection can take a role
+    let second_face = render(QuoteProducer {
+        font: FontSet::new(bold_font, light_font)
+            .unwrap()
+            .username_font(Font::try_from_bytes_and_index(&username_font, 1).unwrap()),
+        font_scale: 40.0,
+        ..testing::test_producer(600, 300)
+    });
+    assert_ne!(second_face, with_role);
+}
+
+#[test]
+fn test_missing_glyphs() {
+    let producer = QuoteProducer {
+        font_scale: 40.0,
+        ..testing::test_producer(600, 300)
+    };
+    // A private use area char, which no font should have
+    let config = ImgConfig::builder()
+        .username("@ksyx\u{F8FF}")
+        .quote("Hi \u{F8FF}\u{F8FF}\n!")
+        .build();
+    let (_, report) = producer.make_image_with_report(&config).unwrap();
+    assert_eq!(report.missing_glyphs, vec!['\u{F8FF}']);
+
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .subtitle("From \u{F8FE}")
+        .build();
+    let (_, report) = producer.make_image_with_report(&config).unwrap();
+    assert_eq!(report.missing_glyphs, vec!['\u{F8FE}']);
+
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let (_, report) = producer.make_image_with_report(&config).unwrap();
+    assert!(report.missing_glyphs.is_empty());
+}
+
+#[test]
+fn test_gradient_width_px() {
+    let config = ImgConfig::builder()
+        .username("")
+        .quote("")
+        .avatar("./assets/avatar.png")
+        .build();
+    let render = |width: Option<u32>, max_alpha| {
+        let producer = QuoteProducer {
+            gradient_max_alpha: max_alpha,
+            ..testing::test_producer(900, 300)
+        };
+        let producer = QuoteProducer {
+            gradient_width_px: width,
+            ..producer
+        };
+        producer.make_raw_image(&config).unwrap()
+    };
+    let column =
+        |image: &RgbaImage, x| (0..300).map(|y| *image.get_pixel(x, y)).collect::<Vec<_>>();
+    let unfaded = render(None, 0);
+
+    // The avatar is 225px wide, only its last 40px fade out
+    let image = render(Some(40), 255);
+    assert_eq!(column(&image, 180), column(&unfaded, 180));
+    assert_ne!(column(&image, 200), column(&unfaded, 200));
+    // The default gradient is 75px wide
+    let image = render(None, 255);
+    assert_ne!(column(&image, 180), column(&unfaded, 180));
+    // The gradient can't be wider than the avatar
+    assert_eq!(render(Some(500), 255), render(Some(225), 255));
+}
+
+#[test]
+fn test_progressive_jpeg() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .avatar("./assets/avatar.png")
+        .quote("Hello")
+        .build();
+    let producer = QuoteProducer {
+        font_scale: 30.0,
+        progressive_jpeg: true,
+        ..testing::test_producer(640, 360)
+    };
+    let buffer = producer.make_image(&config).unwrap();
+    // Start of frame marker for progressive DCT
+    assert!(buffer.windows(2).any(|w| w == [0xFF, 0xC2]));
+    let image = image::load_from_memory(&buffer).unwrap();
+    assert_eq!((image.width(), image.height()), (640, 360));
+}
+
+#[test]
+fn test_quote_from_top() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let producer = QuoteProducer {
+        font_scale: 40.0,
+        quote_vertical: QuoteVertical::FromTop(100),
+        ..testing::test_producer(600, 400)
+    };
+    let image = producer.make_raw_image(&config).unwrap();
+
+    let top = image
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p.0[..3] == [255, 255, 255])
+        .map(|(_, y, _)| y)
+        .min()
+        .unwrap();
+    // The ink of "H" starts a little below the top of the line box
+    assert!((100..120).contains(&top), "quote starts at {top}");
+}
+
+#[test]
+fn test_subtitle() {
+    // Same text everywhere, so the ink heights only differ by scale
+    let config = ImgConfig::builder()
+        .username("Hello")
+        .quote("Hello")
+        .subtitle("Hello")
+        .build();
+    let producer = QuoteProducer {
+        font_scale: 40.0,
+        ..testing::test_producer(800, 400)
+    };
+    let image = producer.make_raw_image(&config).unwrap();
+
+    // Group the rows with any ink into bands
+    let mut bands: Vec<(u32, u32)> = Vec::new();
+    for y in 0..image.height() {
+        if !(0..image.width()).any(|x| image.get_pixel(x, y).0[..3] != [0, 0, 0]) {
+            continue;
+        }
+        match bands.last_mut() {
+            Some((_, bottom)) if *bottom + 1 == y => *bottom = y,
+            _ => bands.push((y, y)),
+        }
+    }
+    // Quote, subtitle and username from top to bottom, without overlap
+    assert_eq!(bands.len(), 3, "{bands:?}");
+    let heights: Vec<u32> = bands.iter().map(|(top, bottom)| bottom - top).collect();
+    assert!(
+        heights[0] > heights[1] && heights[1] > heights[2],
+        "{heights:?}"
+    );
+}
+
+#[test]
+fn test_make_letter_avatar() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Bold.ttc").unwrap();
+    let data = make_letter_avatar("Avimitin", 256, &font, Some(0.8)).unwrap();
+    assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::Png);
+    let avatar = image::load_from_memory(&data).unwrap().into_rgba8();
+    assert_eq!(avatar.dimensions(), (256, 256));
+
+    // Transparent corner, colored circle, and a white letter in the middle
+    assert_eq!(avatar.get_pixel(0, 0).0[3], 0);
+    let circle = avatar.get_pixel(128, 40).0;
+    assert!(circle[3] == 255 && circle != [255, 255, 255, 255]);
+    let letter_columns: Vec<u32> = (0..256)
+        .filter(|&x| (0..256).any(|y| avatar.get_pixel(x, y).0 == [255, 255, 255, 255]))
+        .collect();
+    let (left, right) = (letter_columns[0], *letter_columns.last().unwrap());
+    assert!((left as i32 + right as i32 - 256).abs() <= 16);
+
+    for size in [0, u32::MAX / 3 + 1, 1 << 16] {
+        assert!(matches!(
+            make_letter_avatar("Avimitin", size, &font, Some(0.8)),
+            Err(ErrorKind::InvalidDimensions { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_initials_avatar() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Bold.ttc").unwrap();
+    let producer = QuoteProducer {
+        font: FontSet::single(&font, 0).unwrap(),
+        ..testing::test_producer(900, 300)
+    };
+    // Whether a blank row splits the white letter in the avatar region, like the accent and the E
+    let has_accent = |text: &str| {
+        let avatar = SpooledData::Initials {
+            text: text.to_string(),
+            color: Some([0, 0, 255, 255]),
+        };
+        let config = ImgConfig::builder()
+            .username("")
+            .quote("")
+            .avatar(&avatar)
+            .build();
+        let image = producer.make_raw_image(&config).unwrap();
+        let inked: Vec<bool> = (0..300)
+            .map(|y| (0..300).any(|x| image.get_pixel(x, y).0 == [255, 255, 255, 255]))
+            .collect();
+        let top = inked.iter().position(|&inked| inked).unwrap();
+        let bottom = inked.iter().rposition(|&inked| inked).unwrap();
+        inked[top..bottom].contains(&false)
+    };
+    assert!(!has_accent("Emile"));
+    // The accent is drawn with the E, composed or not
+    assert!(has_accent("Émile"));
+    assert!(has_accent("E\u{301}mile"));
+}
+
+#[test]
+fn test_letter_avatar_letter_ratio() {
+    let font = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Bold.ttc").unwrap();
+    let avatar = SpooledData::Initials {
+        text: "Hello".to_string(),
+        color: Some([0, 0, 255, 255]),
+    };
+    let config = ImgConfig::builder()
+        .username("")
+        .quote("")
+        .avatar(&avatar)
+        .build();
+    // Height of the white letter in the avatar region
+    let letter_height = |ratio| {
+        let producer = QuoteProducer {
+            font: FontSet::single(&font, 0).unwrap(),
+            letter_avatar_letter_ratio: ratio,
+            ..testing::test_producer(900, 300)
+        };
+        let image = producer.make_raw_image(&config).unwrap();
+        (0..300)
+            .filter(|&y| (0..300).any(|x| image.get_pixel(x, y).0 == [255, 255, 255, 255]))
+            .count()
+    };
+    let (default, small) = (letter_height(Some(0.8)), letter_height(Some(0.4)));
+    assert!(small > 0 && small * 3 < default * 2, "{small} {default}");
+}
+
+#[test]
+fn test_normalize_quote_text() {
+    let producer = testing::test_producer(800, 400);
+    let render = |quote: &str| {
+        let config = ImgConfig::builder().username("user").quote(quote).build();
+        producer.make_raw_image(&config).unwrap()
+    };
+    assert_eq!(render("a\tb\r\nc"), render("a    b\nc"));
+    assert_eq!(render("a\u{200B}b"), render("ab"));
+}
+
+#[cfg(all(test, feature = "nfc"))]
+#[test]
+fn test_nfc_quote_text() {
+    let producer = testing::test_producer(800, 400);
+    let render = |quote: &str, username: &str| {
+        let config = ImgConfig::builder().username(username).quote(quote).build();
+        producer.make_raw_image(&config).unwrap()
+    };
+    assert_eq!(
+        render("caf\u{0065}\u{0301}", "Ren\u{0065}\u{0301}"),
+        render("caf\u{00E9}", "Ren\u{00E9}")
+    );
+}
+
+#[test]
+fn test_parsed_fonts() {
+    let bold = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Bold.ttc").unwrap();
+    let light = testing::test_light_font();
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+
+    let from_data = QuoteProducer {
+        font: FontSet::new(&bold, light).unwrap(),
+        ..testing::test_producer(800, 400)
+    };
+    let from_fonts = QuoteProducer {
+        font: FontSet::from_fonts(
+            Font::try_from_vec(bold.clone()).unwrap(),
+            Font::try_from_vec(light.to_vec()).unwrap(),
+        ),
+        ..testing::test_producer(800, 400)
+    };
+    assert_eq!(
+        from_fonts.make_raw_image(&config).unwrap(),
+        from_data.make_raw_image(&config).unwrap()
+    );
+}
+
+#[test]
+fn test_decoded_avatar() {
+    let producer = testing::test_producer(800, 400);
+    let render = |config: &ImgConfig| producer.make_raw_image(config).unwrap();
+
+    let decoded = image::open("./assets/avatar.png").unwrap();
+    let avatar = SpooledData::Decoded(&decoded);
+    let from_image = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .avatar(&avatar)
+        .build();
+    let from_path = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .avatar("./assets/avatar.png")
+        .build();
+    assert_eq!(render(&from_image), render(&from_path));
+}
+
+#[test]
+fn test_feather_avatar() {
+    let avatar = RgbaImage::from_pixel(600, 600, Rgba([255, 255, 255, 255]));
+    let avatar = DynamicImage::ImageRgba8(avatar);
+    let avatar = SpooledData::Decoded(&avatar);
+    let config = ImgConfig::builder()
+        .username("")
+        .quote("")
+        .avatar(&avatar)
+        .build();
+    let render = |feather_px: u32| {
+        let producer = QuoteProducer {
+            gradient_max_alpha: 0,
+            feather_px,
+            ..testing::test_producer(900, 300)
+        };
+        producer.make_raw_image(&config).unwrap()
+    };
+
+    // The white avatar is cropped to 225px wide, and the gradient is disabled to see the
+    // feather alone
+    let (hard, soft) = (render(0), render(8));
+    assert_eq!(hard.get_pixel(224, 150).0[0], 255);
+    assert!(soft.get_pixel(224, 150).0[0] < 64);
+    assert_eq!(soft.get_pixel(215, 150).0[0], 255);
+}
+
+#[test]
+fn test_config_from_parts() {
+    let config = ImgConfig::try_from_parts(
+        Some("Hello".to_string()),
+        Some("@ksyxmeow".to_string()),
+        Some(SpooledData::OnDisk(Path::new("./assets/avatar.png"))),
+    )
+    .unwrap();
+    assert_eq!(config.quote.as_deref(), Some("Hello"));
+    assert!(matches!(config.avatar, Some(SpooledData::OnDisk(_))));
+
+    let missing = ImgConfig::try_from_parts(None, Some("@ksyxmeow".to_string()), None);
+    assert!(matches!(missing, Err(ErrorKind::MissingField("quote"))));
+    let missing = ImgConfig::try_from_parts(Some("Hello".to_string()), None, None);
+    assert!(matches!(missing, Err(ErrorKind::MissingField("username"))));
+}
+
+#[test]
+fn test_render_without_quote() {
+    let producer = testing::test_producer(600, 300);
+    let config = ImgConfig::builder().username("@ksyxmeow").build();
+    assert!(matches!(
+        producer.make_image(&config),
+        Err(ErrorKind::MissingField("quote"))
+    ));
+
+    // Spans replace the quote, and an empty quote is still a quote
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote_spans([("Hello", [255, 0, 0, 255])])
+        .build();
+    producer.make_raw_image(&config).unwrap();
+    let config = ImgConfig::builder().username("@ksyxmeow").quote("").build();
+    producer.make_raw_image(&config).unwrap();
+}
+
+#[test]
+fn test_platform_preset() {
+    let bold = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Bold.ttc").unwrap();
+    let light = testing::test_light_font();
+    let producer = QuoteProducer::preset(Platform::Twitter, FontSet::new(&bold, light).unwrap());
+    assert_eq!(producer.output_size(), (1200, 675));
+    assert_eq!(producer.gap, 20);
+
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let image = producer.make_raw_image(&config).unwrap();
+    assert_eq!(image.dimensions(), (1200, 675));
+}
+
+#[test]
+fn test_avatar_region_width() {
+    let producer = QuoteProducer {
+        gradient_max_alpha: 0,
+        ..testing::test_producer(1200, 400)
+    };
+
+    // A 2:1 white avatar on the black background
+    let avatar = RgbaImage::from_pixel(1000, 500, Rgba([255, 255, 255, 255]));
+    let avatar = DynamicImage::ImageRgba8(avatar);
+    let avatar = SpooledData::Decoded(&avatar);
+    let config = ImgConfig::builder()
+        .username("")
+        .quote("")
+        .avatar(&avatar)
+        .build();
+    let width = producer.avatar_region_width(&config).unwrap();
+    let image = producer.make_raw_image(&config).unwrap();
+    let drawn = (0..image.width())
+        .find(|x| image.get_pixel(*x, 200).0[0] == 0)
+        .unwrap();
+    assert_eq!(width, drawn);
+
+    let config = ImgConfig::builder().username("").quote("").build();
+    assert_eq!(producer.avatar_region_width(&config).unwrap(), 0);
+}
+
+#[test]
+fn test_render_onto() {
+    let producer = QuoteProducer {
+        background_color: [0, 0, 255, 255],
+        ..testing::test_producer(400, 200)
+    };
+    let config = ImgConfig::builder().username("").quote("").build();
+
+    let mut canvas = RgbaImage::from_pixel(1000, 600, Rgba([255, 0, 0, 255]));
+    producer.render_onto(&config, &mut canvas, 100, 50).unwrap();
+    let blue = [0, 0, 255, 255];
+    assert_eq!(canvas.get_pixel(100, 50).0, blue);
+    assert_eq!(canvas.get_pixel(499, 249).0, blue);
+    assert_eq!(canvas.get_pixel(99, 50).0, [255, 0, 0, 255]);
+    assert_eq!(canvas.get_pixel(500, 249).0, [255, 0, 0, 255]);
+    assert_eq!(canvas.get_pixel(100, 250).0, [255, 0, 0, 255]);
+
+    // Clipped at the canvas edge
+    producer
+        .render_onto(&config, &mut canvas, 800, -100)
+        .unwrap();
+    assert_eq!(canvas.get_pixel(999, 0).0, blue);
+    assert_eq!(canvas.get_pixel(999, 100).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn test_supersample() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello, world")
+        .build();
+    let render = |supersample: u32| {
+        let producer = QuoteProducer {
+            font_scale: 40.0,
+            supersample,
+            ..testing::test_producer(512, 512)
+        };
+        producer.make_raw_image(&config).unwrap()
+    };
+    // Total of the differences between horizontal neighbours, the sharper the edges the larger
+    let variation = |image: &RgbaImage| {
+        image
+            .rows()
+            .flat_map(|row| {
+                let row: Vec<i64> = row.map(|p| p.0[0] as i64).collect();
+                row.windows(2)
+                    .map(|w| (w[1] - w[0]).pow(2))
+                    .collect::<Vec<_>>()
+            })
+            .sum::<i64>()
+    };
+    let (single, double) = (render(1), render(2));
+    assert_eq!(double.dimensions(), (512, 512));
+    assert!(variation(&double) < variation(&single));
+}
+
+#[test]
+fn test_supersample_bounds() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello, world")
+        .build();
+    let render = |(width, height): (u32, u32), supersample: u32| {
+        let producer = QuoteProducer {
+            supersample,
+            ..testing::test_producer(width, height)
+        };
+        producer.make_raw_image(&config)
+    };
+    for supersample in [0, 5] {
+        assert!(matches!(
+            render((512, 512), supersample),
+            Err(ErrorKind::InvalidDimensions { .. })
+        ));
+    }
+    assert!(matches!(
+        render((u32::MAX / 2, 512), 4),
+        Err(ErrorKind::InvalidDimensions { .. })
+    ));
+}
+
+#[test]
+fn test_supersample_avatar_offset() {
+    // Red on the left half and blue on the right, panning right shows more blue
+    let avatar = RgbaImage::from_fn(400, 400, |x, _| {
+        if x < 200 {
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([0, 0, 255, 255])
+        }
+    });
+    let avatar = DynamicImage::ImageRgba8(avatar);
+    let avatar = SpooledData::Decoded(&avatar);
+    let config = ImgConfig::builder()
+        .username("")
+        .quote("")
+        .avatar(&avatar)
+        .avatar_zoom(2.0)
+        .avatar_offset((100, 0))
+        .build();
+    let render = |supersample: u32| {
+        let producer = QuoteProducer {
+            gradient: false,
+            supersample,
+            ..testing::test_producer(1200, 400)
+        };
+        producer.make_raw_image(&config).unwrap()
+    };
+    // The offset is in output pixels, so the seam is at the same place in both
+    let (single, double) = (render(1), render(2));
+    let seam = |image: &RgbaImage| (0..400).find(|&x| image.get_pixel(x, 200).0[2] > 128);
+    let seam = (seam(&single).unwrap(), seam(&double).unwrap());
+    assert!(seam.0.abs_diff(seam.1) <= 2, "{seam:?}");
+}
+
+#[test]
+fn test_flatten_avatar() {
+    // A red avatar with a transparent hole in the middle
+    let avatar = RgbaImage::from_fn(400, 400, |x, y| {
+        let hole = (150..250).contains(&x) && (150..250).contains(&y);
+        Rgba([255, 0, 0, if hole { 0 } else { 255 }])
+    });
+    let avatar = DynamicImage::ImageRgba8(avatar);
+    let avatar = SpooledData::Decoded(&avatar);
+    let config = ImgConfig::builder()
+        .username("")
+        .quote("")
+        .avatar(&avatar)
+        .crop_anchor(CropAnchor::Center)
+        .build();
+    let render = |transparent_background: bool, flatten_avatar: bool| {
+        let producer = QuoteProducer {
+            output_format: ImageFormat::Png,
+            background_color: [255, 255, 255, 255],
+            transparent_background,
+            flatten_avatar,
+            ..testing::test_producer(1200, 400)
+        };
+        producer.make_raw_image(&config).unwrap()
+    };
+
+    // The hole is at the middle of the avatar after cropping 1/8 from both sides
+    let hole = |image: &RgbaImage| image.get_pixel(150, 200).0;
+    assert_eq!(hole(&render(false, true)), [255, 255, 255, 255]);
+    assert_eq!(hole(&render(true, false))[3], 0);
+    assert_eq!(hole(&render(true, true)), [255, 255, 255, 255]);
+}
+
+#[test]
+fn test_font_set_covers() {
+    let font = testing::test_font();
+    let fonts = FontSet::single(font, 0).unwrap();
+    assert!(fonts.covers("Hello, world"));
+    // Private use area codepoints are not in the font
+    assert!(!fonts.covers("Hello \u{E000}"));
+    assert_eq!(
+        fonts.uncovered("a\u{E000}b\u{E000}\u{E001}"),
+        ['\u{E000}', '\u{E001}']
+    );
+}
+
+#[test]
+fn test_quote_area_ratio() {
+    let producer = QuoteProducer {
+        gradient_max_alpha: 0,
+        quote_area_ratio: Some(0.6),
+        ..testing::test_producer(1200, 400)
+    };
+
+    for (width, height) in [(400, 400), (1000, 500), (300, 900)] {
+        let avatar = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+        let avatar = DynamicImage::ImageRgba8(avatar);
+        let avatar = SpooledData::Decoded(&avatar);
+        let config = ImgConfig::builder()
+            .username("")
+            .quote("")
+            .avatar(&avatar)
+            .build();
+        assert_eq!(producer.avatar_region_width(&config).unwrap(), 480);
+        // The quote area starts right after the white avatar
+        let image = producer.make_raw_image(&config).unwrap();
+        assert_eq!(image.get_pixel(479, 200).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(480, 200).0, [0, 0, 0, 255]);
+    }
+}
+
+#[test]
+fn test_gradient_start_ratio() {
+    let avatar = RgbaImage::from_pixel(300, 300, Rgba([255, 255, 255, 255]));
+    let avatar = DynamicImage::ImageRgba8(avatar);
+    let avatar = SpooledData::Decoded(&avatar);
+    let config = ImgConfig::builder()
+        .username("")
+        .quote("")
+        .avatar(&avatar)
+        .build();
+    let producer = QuoteProducer {
+        gradient_start_ratio: Some(0.5),
+        ..testing::test_producer(900, 300)
+    };
+    let image = producer.make_raw_image(&config).unwrap();
+
+    // The white avatar is 225px wide, the fade starts at its middle and ends black at its edge
+    let red = |x: u32| image.get_pixel(x, 150).0[0];
+    assert_eq!(red(111), 255);
+    assert!(red(115) < 255);
+    assert!(red(224) < 4);
+}
+
+#[test]
+fn test_make_image_multi() {
+    let producer = QuoteProducer {
+        output_format: ImageFormat::Png,
+        ..testing::test_producer(1920, 1080)
+    };
+    let avatar = SpooledData::TgRandom {
+        id: 1,
+        name: "Ksyx".to_string(),
+    };
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .avatar(&avatar)
+        .build();
+
+    let sizes = [(600, 200), (1200, 400), (500, 500)];
+    let images = producer.make_image_multi(&config, &sizes).unwrap();
+    assert_eq!(images.len(), 3);
+    for (buffer, size) in images.iter().zip(sizes) {
+        let image = image::load_from_memory(buffer).unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), size);
+    }
+    assert!(producer.make_image_multi(&config, &[]).unwrap().is_empty());
+
+    // The trimmed image is scaled down as a whole, not stretched to the requested size
+    let producer = QuoteProducer {
+        output_format: ImageFormat::Png,
+        transparent_background: true,
+        trim: true,
+        ..testing::test_producer(1920, 1080)
+    };
+    let images = producer
+        .make_image_multi(&config, &[(1200, 400), (600, 200)])
+        .unwrap();
+    let [large, small] = [&images[0], &images[1]]
+        .map(|buffer| image::load_from_memory(buffer).unwrap().into_rgba8());
+    assert!(large.width() < 1200);
+    assert!(large.width().abs_diff(small.width() * 2) <= 1);
+    assert!(large.height().abs_diff(small.height() * 2) <= 1);
+}
+
+#[test]
+fn test_fit_long_quote() {
+    let avatar = RgbaImage::from_pixel(300, 300, Rgba([255, 0, 0, 255]));
+    let avatar = DynamicImage::ImageRgba8(avatar);
+    let avatar = SpooledData::Decoded(&avatar);
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("A quote that is way too long to fit in this small image. ".repeat(3))
+        .avatar(&avatar)
+        .build();
+    let producer = || QuoteProducer {
+        font_scale: 60.0,
+        gradient_max_alpha: 0,
+        ..testing::test_producer(900, 300)
+    };
+
+    let (_, report) = producer().make_image_with_report(&config).unwrap();
+    assert!(report.overflowed);
+
+    let producer = QuoteProducer {
+        fit_long_quote: Some(0.5),
+        ..producer()
+    };
+    let (_, report) = producer.make_image_with_report(&config).unwrap();
+    assert!(!report.overflowed);
+    // The red avatar is narrower than the default 225px, but not below the half
+    let image = producer.make_raw_image(&config).unwrap();
+    let is_red = |x: u32| image.get_pixel(x, 20).0 == [255, 0, 0, 255];
+    assert!(is_red(111));
+    assert!(!is_red(224));
+    let width = producer.avatar_region_width(&config).unwrap();
+    assert!((112..225).contains(&width));
+    assert!(is_red(width - 1) && !is_red(width));
+}
+
+#[test]
+fn test_no_gradient() {
+    let avatar = RgbaImage::from_pixel(300, 300, Rgba([255, 255, 255, 255]));
+    let avatar = DynamicImage::ImageRgba8(avatar);
+    let avatar = SpooledData::Decoded(&avatar);
+    let config = ImgConfig::builder()
+        .username("")
+        .quote("")
+        .avatar(&avatar)
+        .build();
+    let producer = QuoteProducer {
+        gradient: false,
+        ..testing::test_producer(900, 300)
+    };
+    let image = producer.make_raw_image(&config).unwrap();
+
+    // The white avatar is 225px wide and stays white up to its edge
+    for x in 0..225 {
+        assert_eq!(image.get_pixel(x, 150).0, [255, 255, 255, 255], "{x}");
+    }
+    assert_eq!(image.get_pixel(225, 150).0, [0, 0, 0, 255]);
+}
+
+#[test]
+fn test_trim() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hi")
+        .build();
+    let producer = QuoteProducer {
+        transparent_background: true,
+        output_format: ImageFormat::Png,
+        trim: true,
+        ..testing::test_producer(900, 300)
+    };
+    let buffer = producer.make_image(&config).unwrap();
+    let image = image::load_from_memory(&buffer).unwrap().into_rgba8();
+    let (width, height) = image.dimensions();
+    assert!(width < 900 && height < 300, "{width}x{height}");
+    // The text touches every edge of the trimmed image
+    assert!((0..height).any(|y| image.get_pixel(0, y).0[3] > 0));
+    assert!((0..width).any(|x| image.get_pixel(x, 0).0[3] > 0));
+}
+
+#[test]
+fn test_dpi() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let producer = || QuoteProducer {
+        font_scale: 20.0,
+        dpi: Some(300),
+        ..testing::test_producer(320, 180)
+    };
+
+    let png_producer = QuoteProducer {
+        output_format: ImageFormat::Png,
+        ..producer()
+    };
+    let buffer = png_producer.make_image(&config).unwrap();
+    let reader = png::Decoder::new(buffer.as_slice()).read_info().unwrap();
+    let dims = reader.info().pixel_dims.unwrap();
+    assert_eq!(
+        (dims.xppu, dims.yppu, dims.unit),
+        (11811, 11811, png::Unit::Meter)
+    );
+
+    for progressive in [false, true] {
+        let producer = QuoteProducer {
+            progressive_jpeg: progressive,
+            embed_metadata: true,
+            ..producer()
+        };
+        let buffer = producer.make_image(&config).unwrap();
+        // The JFIF segment has the units and the densities after the identifier and version
+        let start = buffer.windows(5).position(|w| w == b"JFIF\0").unwrap();
+        assert_eq!(buffer[start + 7..start + 12], [1, 1, 44, 1, 44]);
+        image::load_from_memory(&buffer).unwrap();
+    }
+}
+
+#[test]
+fn test_extreme_avatar_ratio() {
+    let producer = testing::test_producer(900, 300);
+    for (width, height) in [(5000, 100), (100, 5000)] {
+        let avatar = DynamicImage::ImageRgba8(RgbaImage::new(width, height));
+        let avatar = SpooledData::Decoded(&avatar);
+        let config = ImgConfig::builder()
+            .username("@ksyxmeow")
+            .quote("Hello")
+            .avatar(&avatar)
+            .build();
+        let region_width = producer.avatar_region_width(&config).unwrap();
+        assert!((1..=450).contains(&region_width), "{region_width}");
+        let image = producer.make_raw_image(&config).unwrap();
+        assert_eq!(image.dimensions(), (900, 300));
+    }
+}
+
+#[test]
+fn test_auto_text_color() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    for (background, ink) in [
+        ([255, 255, 255, 255], [0, 0, 0]),
+        ([20, 20, 60, 255], [255; 3]),
+    ] {
+        let producer = QuoteProducer {
+            background_color: background,
+            auto_text_color: true,
+            ..testing::test_producer(600, 300)
+        };
+        let image = producer.make_raw_image(&config).unwrap();
+        // The quote sits above the middle line, the username is below it
+        let quote_pixels = (0..150).flat_map(|y| (0..600).map(move |x| (x, y)));
+        let inked = quote_pixels
+            .filter(|&(x, y)| image.get_pixel(x, y).0[..3] == ink)
+            .count();
+        assert!(inked > 50, "{background:?}: {inked}");
+    }
+
+    // The blur fill of a dark avatar stays left to the quote, which is drawn over the light gray
+    let avatar = DynamicImage::ImageRgba8(RgbaImage::from_pixel(50, 300, Rgba([0, 0, 0, 255])));
+    let avatar = SpooledData::Decoded(&avatar);
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .avatar(&avatar)
+        .build();
+    let producer = QuoteProducer {
+        background_color: [150, 150, 150, 255],
+        background_fill: BackgroundFill::BlurAvatar,
+        auto_text_color: true,
+        ..testing::test_producer(600, 300)
+    };
+    let image = producer.make_raw_image(&config).unwrap();
+    let quote_pixels = (0..150).flat_map(|y| (200..600).map(move |x| (x, y)));
+    let inked = quote_pixels
+        .filter(|&(x, y)| image.get_pixel(x, y).0[..3] == [0; 3])
+        .count();
+    assert!(inked > 50, "{inked}");
+}
+
+#[test]
+fn test_make_image_with_mime() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    for (format, mime) in [
+        (ImageFormat::Jpeg, "image/jpeg"),
+        (ImageFormat::Png, "image/png"),
+        (ImageFormat::WebP, "image/webp"),
+    ] {
+        let producer = QuoteProducer {
+            output_format: format,
+            ..testing::test_producer(320, 180)
+        };
+        let (buffer, content_type) = producer.make_image_with_mime(&config).unwrap();
+        assert_eq!(content_type, mime);
+        assert_eq!(image::guess_format(&buffer).unwrap(), format);
+    }
+}
+
+#[test]
+fn test_avatar_from_reader() {
+    let mut reader = Cursor::new(std::fs::read("./assets/avatar.png").unwrap());
+    let avatar = SpooledData::from_reader(&mut reader).unwrap();
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .avatar(&avatar)
+        .build();
+    let producer = testing::test_producer(900, 300);
+    assert_eq!(producer.avatar_region_width(&config).unwrap(), 225);
+    producer.make_image(&config).unwrap();
+
+    // Read errors are reported like the errors of reading an avatar file
+    struct Broken;
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("connection reset"))
+        }
+    }
+    assert!(matches!(
+        SpooledData::from_reader(&mut Broken),
+        Err(ErrorKind::AvatarDecode(ImageError::IoError(_)))
+    ));
+}
+
+#[test]
+fn test_max_output_bytes() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .avatar("./assets/avatar.png")
+        .quote("Hello")
+        .build();
+    let producer = || QuoteProducer {
+        font_scale: 30.0,
+        ..testing::test_producer(640, 360)
+    };
+    let full_size = producer().make_image(&config).unwrap().len();
+
+    for progressive in [false, true] {
+        let limit = full_size * 2 / 3;
+        let producer = QuoteProducer {
+            progressive_jpeg: progressive,
+            max_output_bytes: Some(limit),
+            ..producer()
+        };
+        let buffer = producer.make_image(&config).unwrap();
+        assert!(buffer.len() <= limit, "{} > {limit}", buffer.len());
+        image::load_from_memory(&buffer).unwrap();
+    }
+
+    let producer = QuoteProducer {
+        max_output_bytes: Some(100),
+        ..producer()
+    };
+    assert!(matches!(
+        producer.make_image(&config),
+        Err(ErrorKind::OutputTooLarge { limit: 100, .. })
+    ));
+}
+
+#[cfg(all(test, feature = "debug-steps"))]
+#[test]
+fn test_step_hook() {
+    use std::sync::{Arc, Mutex};
+
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .avatar("./assets/avatar.png")
+        .quote("Hello")
+        .build();
+    let steps = Arc::new(Mutex::new(Vec::new()));
+    let recorded = steps.clone();
+    let producer = QuoteProducer {
+        step_hook: Some(Box::new(move |label, image| {
+            recorded
+                .lock()
+                .unwrap()
+                .push((label.to_string(), image.dimensions()));
+        })),
+        ..testing::test_producer(640, 360)
+    };
+    producer.make_image(&config).unwrap();
+
+    let steps = steps.lock().unwrap();
+    let labels: Vec<&str> = steps.iter().map(|(label, _)| label.as_str()).collect();
+    assert_eq!(labels, ["background", "avatar", "gradient", "quotes"]);
+    assert!(steps.iter().all(|(_, size)| *size == (640, 360)));
+}
+
+#[test]
+fn test_gap_em() {
+    let config = ImgConfig::builder().username("Name").quote("").build();
+    // The username is left aligned at the gap, find where its ink starts
+    let ink_left = |font_scale: f32| {
+        let producer = QuoteProducer {
+            font_scale,
+            gap_em: Some(1.0),
+            username_align: Horizontal::Left,
+            ..testing::test_producer(800, 400)
+        };
+        let image = producer.make_raw_image(&config).unwrap();
+        (0..800)
+            .find(|&x| (0..400).any(|y| image.get_pixel(x, y).0[0] > 0))
+            .unwrap()
+    };
+    let (small, large) = (ink_left(40.0), ink_left(80.0));
+    assert!((38..=44).contains(&small), "{small}");
+    assert!((78..=86).contains(&large), "{large}");
+}
+
+#[test]
+fn test_chroma_subsampling() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote_spans([("Red ", [255, 0, 0, 255]), ("blue", [0, 64, 255, 255])])
+        .build();
+    let producer = || QuoteProducer {
+        font_scale: 60.0,
+        ..testing::test_producer(640, 360)
+    };
+    let reference = producer().make_raw_image(&config).unwrap();
+    // Sum the error of the color channels, against the image before encoding
+    let color_error = |sampling| {
+        let producer = QuoteProducer {
+            chroma_subsampling: Some(sampling),
+            ..producer()
+        };
+        let buffer = producer.make_image(&config).unwrap();
+        let image = image::load_from_memory(&buffer).unwrap().into_rgba8();
+        let chroma = |p: &Rgba<u8>| {
+            let [r, g, b, _] = p.0.map(|c| c as i64);
+            (r - g, b - g)
+        };
+        image
+            .pixels()
+            .zip(reference.pixels())
+            .map(|(p, q)| {
+                let ((a, b), (c, d)) = (chroma(p), chroma(q));
+                (a - c).abs() + (b - d).abs()
+            })
+            .sum::<i64>()
+    };
+    let full = color_error(ChromaSubsampling::Yuv444);
+    let half = color_error(ChromaSubsampling::Yuv420);
+    assert!(full * 3 < half * 2, "4:4:4 {full}, 4:2:0 {half}");
+}
+
+#[test]
+fn test_shared_font_set() {
+    let bold = std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Bold.ttc").unwrap();
+    let light = testing::test_light_font();
+    assert!(matches!(
+        FontSet::new(b"not a font", light),
+        Err(ErrorKind::InvalidFont(_))
+    ));
+
+    let fonts = FontSet::new(&bold, light).unwrap();
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .build();
+    let small = QuoteProducer {
+        font: fonts.clone(),
+        ..testing::test_producer(800, 400)
+    };
+    let large = QuoteProducer {
+        font: fonts,
+        ..testing::test_producer(1200, 600)
+    };
+    let from_data = QuoteProducer {
+        font: FontSet::new(&bold, light).unwrap(),
+        ..testing::test_producer(800, 400)
+    };
+    assert_eq!(
+        small.make_raw_image(&config).unwrap(),
+        from_data.make_raw_image(&config).unwrap()
+    );
+    assert_eq!(
+        large.make_raw_image(&config).unwrap().dimensions(),
+        (1200, 600)
+    );
+}
+
+#[test]
+fn test_username_icon() {
+    let producer = testing::test_producer(800, 400);
+    let icon = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255])));
+    let icon = SpooledData::Decoded(&icon);
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .username_icon(&icon)
+        .build();
+    let image = producer.make_raw_image(&config).unwrap();
+    // Only the icon is red, in the username area under the middle line
+    let red: Vec<u32> = image
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p.0 == [255, 0, 0, 255])
+        .map(|(_, y, _)| y)
+        .collect();
+    assert!(!red.is_empty() && red.iter().all(|&y| y > 200));
+
+    // A letter icon is as tall as the image icon, instead of a small circle in a tall strip
+    let height = |rows: &[u32]| rows.iter().max().unwrap() - rows.iter().min().unwrap() + 1;
+    let letter = SpooledData::Initials {
+        text: "ksyx".to_string(),
+        color: Some([0, 0, 255, 255]),
+    };
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("Hello")
+        .username_icon(&letter)
+        .build();
+    let image = producer.make_raw_image(&config).unwrap();
+    let blue: Vec<u32> = image
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p.0 == [0, 0, 255, 255])
+        .map(|(_, y, _)| y)
+        .collect();
+    assert!(!blue.is_empty());
+    assert!(height(&blue) * 4 >= height(&red) * 3, "{blue:?}");
+
+    let broken = SpooledData::InMem(b"not an image");
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("")
+        .username_icon(&broken)
+        .build();
+    assert!(matches!(
+        producer.make_raw_image(&config),
+        Err(ErrorKind::IconDecode(_))
+    ));
+}
+
+#[test]
+fn test_deterministic() {
+    let avatar = SpooledData::TgRandom {
+        id: 13,
+        name: "ksyx".to_string(),
+    };
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .avatar(&avatar)
+        .quote("大家好，今天来点大家想看的东西。")
+        .build();
+    // The filter type bytes in front of each row of the inflated PNG image data
+    let png_filters = |buffer: &[u8]| {
+        let mut decoder = png::StreamingDecoder::new();
+        let (mut data, mut buffer) = (Vec::new(), buffer);
+        loop {
+            let (read, decoded) = decoder.update(buffer, &mut data).unwrap();
+            buffer = &buffer[read..];
+            if let png::Decoded::ImageEnd = decoded {
+                break;
+            }
+        }
+        data.chunks(800 * 4 + 1)
+            .map(|row| row[0])
+            .collect::<Vec<_>>()
+    };
+    for format in [ImageFormat::Png, ImageFormat::Jpeg] {
+        let producer = || QuoteProducer {
+            output_format: format,
+            deterministic: true,
+            ..testing::test_producer(800, 400)
+        };
+        // Warm caches of the first producer don't change the output of the second render
+        let first = producer();
+        let cold = first.make_image(&config).unwrap();
+        let warm = first.make_image(&config).unwrap();
+        let fresh = producer().make_image(&config).unwrap();
+        assert!(cold == warm && cold == fresh, "{format:?}");
+
+        let decoded = image::load_from_memory(&cold).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (800, 400));
+    }
+
+    // The default PNG encoder picks a filter per row, the pinned one always uses Sub
+    let producer = |deterministic| QuoteProducer {
+        output_format: ImageFormat::Png,
+        deterministic,
+        ..testing::test_producer(800, 400)
+    };
+    let pinned = png_filters(&producer(true).make_image(&config).unwrap());
+    assert_eq!(pinned.len(), 400);
+    assert!(pinned
+        .iter()
+        .all(|&filter| filter == png::FilterType::Sub as u8));
+    let default = png_filters(&producer(false).make_image(&config).unwrap());
+    assert!(default
+        .iter()
+        .any(|&filter| filter != png::FilterType::Sub as u8));
+}
+
+#[test]
+fn test_quote_gradient() {
+    let config = ImgConfig::builder()
+        .username("@ksyxmeow")
+        .quote("HIH")
+        .build();
+    let producer = QuoteProducer {
+        font_scale: 120.0,
+        quote_gradient: Some((Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]))),
+        ..testing::test_producer(800, 400)
+    };
+    let image = producer.make_raw_image(&config).unwrap();
+    // The quote is above the middle line, the first strongly colored row is its top
+    let colored = |p: &Rgba<u8>| p.0[0].abs_diff(p.0[2]) > 100;
+    let rows: Vec<[u8; 4]> = image
+        .enumerate_pixels()
+        .filter(|(_, y, p)| *y < 200 && colored(p))
+        .map(|(_, _, p)| p.0)
+        .collect();
+    let (top, bottom) = (rows.first().unwrap(), rows.last().unwrap());
+    assert!(
+        top[0] > top[2] && bottom[2] > bottom[0],
+        "{top:?} {bottom:?}"
+    );
+}
@@ -0,0 +1,99 @@
+//! Helpers to catch rendering regressions in tests.
+
+use crate::{FontSet, QuoteProducer};
+use image::RgbaImage;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// The Noto Sans CJK Medium font data, read once for all the tests.
+pub(crate) fn test_font() -> &'static [u8] {
+    static FONT: OnceLock<Vec<u8>> = OnceLock::new();
+    FONT.get_or_init(|| std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Medium.ttc").unwrap())
+}
+
+/// The Noto Sans CJK Light font data, read once for all the tests.
+pub(crate) fn test_light_font() -> &'static [u8] {
+    static FONT: OnceLock<Vec<u8>> = OnceLock::new();
+    FONT.get_or_init(|| std::fs::read("/usr/share/fonts/noto-cjk/NotoSansCJK-Light.ttc").unwrap())
+}
+
+/// Draw everything with [`test_font`].
+pub(crate) fn test_fonts() -> FontSet<'static> {
+    FontSet::single(test_font(), 0).unwrap()
+}
+
+/// Draw the bold text with [`test_font`] and the light text with [`test_light_font`].
+pub(crate) fn test_font_pair() -> FontSet<'static> {
+    FontSet::new(test_font(), test_light_font()).unwrap()
+}
+
+/// A producer of `width`x`height` images drawn with [`test_fonts`], with the other options at
+/// their defaults. Tests change the options they check with the struct update syntax.
+pub(crate) fn test_producer(width: u32, height: u32) -> QuoteProducer<'static> {
+    QuoteProducer::builder()
+        .font(test_fonts())
+        .output_size(width, height)
+        .build()
+}
+
+/// Peak signal-to-noise ratio of two images in dB, over all four channels. Identical images have
+/// infinite PSNR.
+pub(crate) fn psnr(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let squared_error: f64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(x, y)| (*x as f64 - *y as f64).powi(2))
+        .sum();
+    let mse = squared_error / a.as_raw().len() as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Assert the two images have the same size and are close enough, that is, their PSNR is at
+/// least `min_psnr` dB.
+#[track_caller]
+pub(crate) fn assert_image_approx_eq(actual: &RgbaImage, expected: &RgbaImage, min_psnr: f64) {
+    assert_eq!(
+        actual.dimensions(),
+        expected.dimensions(),
+        "image dimensions differ"
+    );
+    let psnr = psnr(actual, expected);
+    assert!(
+        psnr >= min_psnr,
+        "images differ too much: PSNR {psnr:.2} dB is below {min_psnr} dB"
+    );
+}
+
+/// Compare the image with the reference `assets/golden/<name>.png`. Run the tests with
+/// `UPDATE_GOLDEN=1` to write the references after an intended rendering change.
+///
+/// Text rendering depends on the fonts installed on the machine, so keep text out of the
+/// reference images.
+#[track_caller]
+pub(crate) fn assert_golden(name: &str, actual: &RgbaImage) {
+    let path: PathBuf = ["assets", "golden", &format!("{name}.png")]
+        .iter()
+        .collect();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        actual.save(&path).unwrap();
+        return;
+    }
+
+    let expected = image::open(&path)
+        .unwrap_or_else(|err| panic!("fail to open {}: {err}", path.display()))
+        .into_rgba8();
+    // Allow tiny differences from float rounding across platforms
+    assert_image_approx_eq(actual, &expected, 40.0);
+}
+
+#[test]
+fn test_psnr() {
+    let a = RgbaImage::from_pixel(10, 10, image::Rgba([0, 0, 0, 255]));
+    let mut b = a.clone();
+    assert!(psnr(&a, &b).is_infinite());
+    b.put_pixel(0, 0, image::Rgba([255, 255, 255, 255]));
+    assert!(psnr(&a, &b) < 30.0);
+    assert!(psnr(&a, &b) > 20.0);
+}